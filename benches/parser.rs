@@ -0,0 +1,30 @@
+use cb_twitchchatbot_rust::messages::{
+    irc_message::{ IrcMessage, IrcMessageRef },
+    private_message::PrivateMessageRequest,
+};
+use criterion::{ black_box, criterion_group, criterion_main, Criterion };
+
+const CHAT_LINE: &str =
+    "@badge-info=subscriber/8;badges=moderator/1,subscriber/6;client-nonce=b2a8b0f6;color=#0D4200;display-name=Viewer;emotes=25:0-4,12-16/1902:6-10;first-msg=0;flags=;id=b34ccfc7-4977-403a-8a94-33c6bac34fb8;mod=1;returning-chatter=0;room-id=12345678;subscriber=1;tmi-sent-ts=1507246572675;turbo=0;user-id=1337;user-type=mod :viewer!viewer@viewer.tmi.twitch.tv PRIVMSG #channel :Kappa Keepo Kappa";
+
+fn parse_chat_line(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse_chat_line");
+
+    group.bench_function("PrivateMessageRequest::new", |b| {
+        b.iter(|| PrivateMessageRequest::new(black_box(CHAT_LINE)).unwrap())
+    });
+    group.bench_function("IrcMessage::parse", |b| {
+        b.iter(|| IrcMessage::parse(black_box(CHAT_LINE)).unwrap())
+    });
+    group.bench_function("IrcMessageRef::parse", |b| {
+        b.iter(|| {
+            let message = IrcMessageRef::parse(black_box(CHAT_LINE)).unwrap();
+            (message.command(), message.tag("display-name"), message.last_param())
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, parse_chat_line);
+criterion_main!(benches);
@@ -0,0 +1,19 @@
+#![no_main]
+
+use cb_twitchchatbot_rust::messages::irc_message::{ IrcMessage, IrcMessageRef };
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|line: &str| {
+    let Ok(message) = IrcMessage::parse(line) else {
+        return;
+    };
+    let borrowed = IrcMessageRef::parse(line).expect("Borrowed parser rejected a valid line");
+    assert_eq!(borrowed.to_owned_message(), message);
+
+    // A line break ends the message on the wire, it cannot survive a round trip
+    if line.contains(['\r', '\n']) {
+        return;
+    }
+    let reparsed = IrcMessage::parse(&message.to_string()).expect("Serialized message did not parse");
+    assert_eq!(reparsed, message);
+});
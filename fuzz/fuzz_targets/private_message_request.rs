@@ -0,0 +1,8 @@
+#![no_main]
+
+use cb_twitchchatbot_rust::messages::private_message::PrivateMessageRequest;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|line: &str| {
+    let _ = PrivateMessageRequest::new(line);
+});
@@ -1,115 +1,295 @@
-use std::{ collections::HashMap, sync::{ Arc, Mutex } };
+use std::{ sync::Arc, time::Duration };
 
-use tokio::sync::mpsc;
-use tracing::{ error, info };
+use tokio::{ sync::{ mpsc, watch }, task::JoinHandle, time::Instant };
+use tracing::{ error, info, warn };
 
 use crate::{
-    config::command_parser::Commands,
-    messages::{ bot_command::BotCommand, private_message::PrivateMessageRequest },
-    tcp_handler::TcpHandler,
+    config::{ command_parser::Commands, connection::ConnectionConfig, watcher::watch_commands },
+    cooldown_store,
+    error::{ ConnectError, Result },
+    messages::{
+        bot_command::BotCommand,
+        cooldown::CooldownTracker,
+        irc_message::IrcMessageRef,
+        private_message::{ Command, PrivateMessageRequest, PrivateMessageResponse },
+        tags::Tags,
+        user_notice::UserNoticeEvent,
+        welcome::Welcomer,
+    },
+    rate_limiter::OverflowPolicy,
+    tcp_handler::{ BotMessage, TcpHandler },
 };
 
+/// How often cooldowns that ran out are dropped from memory.
+const EVICTION_INTERVAL: Duration = Duration::from_secs(60);
+
 pub struct ChatBot {
-    sender: mpsc::UnboundedSender<String>,
+    sender: mpsc::UnboundedSender<BotMessage>,
     receiver: mpsc::UnboundedReceiver<String>,
-    commands: Commands,
-    last_triggers: Arc<Mutex<HashMap<String, HashMap<String, u64>>>>,
+    tcp_handler: JoinHandle<std::result::Result<(), ConnectError>>,
+    commands: watch::Receiver<Arc<Commands>>,
+    cooldowns: CooldownTracker,
+    last_eviction: Instant,
+    welcomer: Welcomer,
+    cooldown_state_file: Option<String>,
+}
+
+/// Cloneable handle to control a running [`ChatBot`] from other tasks.
+#[derive(Clone)]
+pub struct ChatBotHandle {
+    sender: mpsc::UnboundedSender<BotMessage>,
+}
+
+impl ChatBotHandle {
+    pub fn join(&self, channel: &str) -> Result<()> {
+        Ok(self.sender.send(BotMessage::Join(channel.to_string()))?)
+    }
+
+    pub fn part(&self, channel: &str) -> Result<()> {
+        Ok(self.sender.send(BotMessage::Part(channel.to_string()))?)
+    }
 }
 
 impl ChatBot {
-    pub fn new(nickname: String, oauth_token: String, channel: String, file_path: String) -> Self {
+    pub fn new(
+        connection: ConnectionConfig,
+        nickname: String,
+        oauth_token: String,
+        channels: Vec<String>,
+        file_path: String,
+        overflow_policy: OverflowPolicy
+    ) -> Result<Self> {
+        let commands = Commands::new(&file_path)?;
+
         let (from_bot_sender, from_bot_receiver) = mpsc::unbounded_channel();
 
         let (from_tcp_sender, from_tcp_receiver) = mpsc::unbounded_channel();
 
-        tokio::spawn(async move {
+        let tcp_handler = tokio::spawn(async move {
             let mut tcp_handler = TcpHandler::new(
+                connection,
                 &nickname,
                 &oauth_token,
-                &channel,
+                &channels,
+                overflow_policy,
                 from_tcp_sender
             );
-            tcp_handler.run(from_bot_receiver).await;
+            tcp_handler.run(from_bot_receiver).await
         });
 
-        let commands = Commands::new(&file_path);
-
-        let last_triggers: Arc<Mutex<HashMap<String, HashMap<String, u64>>>> = Arc::new(
-            Mutex::new(HashMap::new())
-        );
+        let cooldowns = CooldownTracker::default();
+        let commands = watch_commands(file_path, commands, cooldowns.clone());
+        let welcomer = Welcomer::with_clock(cooldowns.clock());
 
-        Self {
+        Ok(Self {
             sender: from_bot_sender,
             receiver: from_tcp_receiver,
+            tcp_handler,
             commands,
-            last_triggers,
+            cooldowns,
+            last_eviction: Instant::now(),
+            welcomer,
+            cooldown_state_file: None,
+        })
+    }
+
+    /// Restores cooldowns from `state_file` and saves them there periodically
+    /// and when the bot stops.
+    pub fn persist_cooldowns(&mut self, state_file: &str) {
+        cooldown_store::persist_cooldowns(
+            state_file.to_string(),
+            self.cooldowns.clone(),
+            self.commands.clone()
+        );
+        self.cooldown_state_file = Some(state_file.to_string());
+    }
+
+    pub fn handle(&self) -> ChatBotHandle {
+        ChatBotHandle {
+            sender: self.sender.clone(),
         }
     }
+
     fn handle_bot_command(
         &self,
         bot_command: &BotCommand,
-        tags: &Option<HashMap<String, serde_json::Value>>,
+        tags: &Option<Tags>,
         channel: &str
-    ) -> Option<String> {
-        let display_name = tags
-            .as_ref()
-            .and_then(|tags| tags.get("display-name").and_then(|v| v.as_str()));
-
-        if let Some(display_name) = display_name {
-            bot_command
-                .parse(display_name, channel, self.commands.clone(), self.last_triggers.clone())
-                .map(|response| format!("{}", response))
+    ) -> Option<PrivateMessageResponse> {
+        if let Some(Tags::Privmsg(tags)) = tags {
+            let commands = self.commands.borrow().clone();
+            bot_command.parse(tags, channel, &commands, &self.cooldowns)
         } else {
-            error!("No display_name in handle_bot_command");
+            error!("No PRIVMSG tags in handle_bot_command");
             None
         }
     }
 
-    fn handle_message(&self, private_message_request: &PrivateMessageRequest) -> Option<String> {
-        private_message_request.command.as_ref().and_then(|command| {
-            match command.command.as_str() {
-                "PRIVMSG" => {
-                    if let Some(ref bot_command) = command.bot_command {
-                        if let Some(ref channel) = command.channel {
-                            return self.handle_bot_command(
-                                bot_command,
-                                &private_message_request.tags,
-                                channel.as_str()
-                            );
-                        }
-                    }
-                    None
-                }
-                "PING" => {
-                    info!(
-                        "{} - {}",
-                        command.command,
-                        private_message_request.parameters.as_deref().unwrap_or_default()
-                    );
-                    private_message_request.parameters
-                        .as_ref()
-                        .map(|parameters| { format!("PONG {}", parameters) })
-                }
-                _ => {
-                    info!(
-                        "Unhandled: {} - {}",
-                        command.command,
-                        private_message_request.parameters.as_deref().unwrap_or_default()
-                    );
-                    None
-                }
+    fn handle_user_notice(
+        &self,
+        event: &UserNoticeEvent,
+        tags: &Option<Tags>,
+        channel: &str,
+        message: Option<&str>
+    ) -> Option<PrivateMessageResponse> {
+        if event.is_part_of_community_gift() {
+            info!("Skipping {} event in {}, the community gift was already answered", event.kind(), channel);
+            return None;
+        }
+        let commands = self.commands.borrow().clone();
+        let Some(event_response) = commands.event_response(event.kind(), channel) else {
+            info!("No response for {} event in {}", event.kind(), channel);
+            return None;
+        };
+        let sender = tags.as_ref().and_then(Tags::display_name).unwrap_or_default();
+        let response = event.render(&event_response.response, sender, message.unwrap_or_default());
+
+        info!("Handled: {} event in {}", event.kind(), channel);
+        Some(PrivateMessageResponse::from(channel, &response))
+    }
+
+    fn handle_welcome(&mut self, tags: &Option<Tags>, channel: &str) -> Option<PrivateMessageResponse> {
+        let Some(Tags::Privmsg(tags)) = tags else {
+            return None;
+        };
+        let commands = self.commands.borrow().clone();
+        let welcome = commands.welcome(channel)?;
+        self.welcomer.welcome(welcome, tags, channel)
+    }
+
+    /// Whether a chat line without a bot command may still need a welcome.
+    fn may_need_welcome(&self, message: &IrcMessageRef) -> bool {
+        let commands = self.commands.borrow();
+        let Some(welcome) = message.param(0).and_then(|channel| commands.welcome(channel)) else {
+            return false;
+        };
+        welcome.first_in_stream || message.tag("first-msg").is_some_and(|first_msg| first_msg == "1")
+    }
+
+    /// Responses to a message, a chat line may get both a welcome and a command response.
+    fn handle_message(&mut self, private_message_request: &PrivateMessageRequest) -> Vec<BotMessage> {
+        let Some(command) = private_message_request.command.as_ref() else {
+            return Vec::new();
+        };
+        if command.command != "PRIVMSG" {
+            return self.handle_command(command, private_message_request).into_iter().collect();
+        }
+        let Some(channel) = command.channel.as_deref() else {
+            return Vec::new();
+        };
+
+        // Twitch marks only the very first message, which may well be a command
+        let welcome = self.handle_welcome(&private_message_request.tags, channel);
+        let response = command.bot_command.as_ref().and_then(|bot_command| {
+            self.handle_bot_command(bot_command, &private_message_request.tags, channel)
+        });
+        welcome.into_iter().chain(response).map(BotMessage::Privmsg).collect()
+    }
+
+    /// Handles every command besides PRIVMSG.
+    fn handle_command(
+        &mut self,
+        command: &Command,
+        private_message_request: &PrivateMessageRequest
+    ) -> Option<BotMessage> {
+        match command.command.as_str() {
+            // Without the membership capability Twitch only echoes the
+            // bot's own JOIN and PART
+            "JOIN" | "PART" => {
+                let channel = command.channel.as_deref()?;
+                self.welcomer.forget_channel(channel);
+                None
             }
-        })
+            "PING" => {
+                info!(
+                    "{} - {}",
+                    command.command,
+                    private_message_request.parameters.as_deref().unwrap_or_default()
+                );
+                private_message_request.parameters
+                    .as_ref()
+                    .map(|parameters| BotMessage::Raw(format!("PONG {}", parameters)))
+            }
+            "USERSTATE" => {
+                let channel = command.channel.clone()?;
+                let privileged = private_message_request.tags
+                    .as_ref()
+                    .and_then(Tags::badges)
+                    .is_some_and(|badges| badges.is_privileged());
+                Some(BotMessage::UserState { channel, privileged })
+            }
+            "USERNOTICE" => {
+                let event = command.user_notice.as_ref()?;
+                let channel = command.channel.as_deref()?;
+                self.handle_user_notice(
+                    event,
+                    &private_message_request.tags,
+                    channel,
+                    private_message_request.parameters.as_deref()
+                ).map(BotMessage::Privmsg)
+            }
+            "RECONNECT" => {
+                info!("{} - Twitch requested a reconnect", command.command);
+                Some(BotMessage::Reconnect)
+            }
+            _ => {
+                info!(
+                    "Unhandled: {} - {}",
+                    command.command,
+                    private_message_request.parameters.as_deref().unwrap_or_default()
+                );
+                None
+            }
+        }
     }
 
-    pub async fn run(&mut self) {
+    /// Handles incoming messages until the connection is given up for good.
+    pub async fn run(&mut self) -> Result<()> {
         while let Some(raw_message) = self.receiver.recv().await {
-            let private_message_request = PrivateMessageRequest::new(&raw_message);
-            if let Some(message) = self.handle_message(&private_message_request) {
+            let message = match IrcMessageRef::parse(&raw_message) {
+                Ok(message) => message,
+                Err(error) => {
+                    warn!("{}", error);
+                    continue;
+                }
+            };
+            // Most lines in busy channels are plain chat, skip them before
+            // building the owned request
+            if
+                message.command() == "PRIVMSG" &&
+                !message.last_param().is_some_and(|text| text.starts_with('!')) &&
+                !self.may_need_welcome(&message)
+            {
+                continue;
+            }
+            let private_message_request = match PrivateMessageRequest::from_irc(message.to_owned_message()) {
+                Ok(private_message_request) => private_message_request,
+                Err(error) => {
+                    warn!("{}", error);
+                    continue;
+                }
+            };
+            for message in self.handle_message(&private_message_request) {
                 if let Err(error) = self.sender.send(message) {
                     error!("Sending to tcp_handler from chat_bot failed {}", error);
                 }
             }
+            if self.last_eviction.elapsed() >= EVICTION_INTERVAL {
+                self.cooldowns.evict_expired(&self.commands.borrow());
+                self.last_eviction = Instant::now();
+            }
+        }
+
+        if let Some(state_file) = &self.cooldown_state_file {
+            let commands = self.commands.borrow().clone();
+            if let Err(error) = cooldown_store::save(state_file, &self.cooldowns, &commands) {
+                error!("Saving cooldowns to {} failed: {}", state_file, error);
+            }
         }
+
+        let result = (&mut self.tcp_handler).await.unwrap_or_else(|error| {
+            std::panic::resume_unwind(error.into_panic())
+        });
+        Ok(result?)
     }
 }
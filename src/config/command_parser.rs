@@ -1,51 +1,440 @@
-use std::{ collections::HashSet, fs::File };
+use std::{ collections::HashSet, fmt::Display, fs::File, str::FromStr, time::Duration };
 use std::io::BufReader;
 use regex::Regex;
-use serde::{ Deserialize, Serialize };
-use tracing::info;
+use serde::{ de, Deserialize, Deserializer, Serialize, Serializer };
+use tracing::{ info, warn };
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+use crate::{
+    config::enabled_in,
+    error::{ Error, Result },
+    messages::{ tags::PrivmsgTags, user_notice::{ event_placeholders, COMMON_PLACEHOLDERS } },
+};
+
+/// Who may trigger a command. Roles include the ones above them, so a
+/// moderator may use subscriber commands.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    #[default]
+    Everyone,
+    /// IRC does not expose follows, so this admits anyone who is not writing
+    /// their first message in the channel.
+    Follower,
+    Subscriber,
+    Vip,
+    Moderator,
+    Broadcaster,
+    /// Only the listed user ids, plus the broadcaster.
+    Users(Vec<String>),
+}
+
+impl Permission {
+    pub fn allows(&self, tags: &PrivmsgTags) -> bool {
+        let badges = &tags.badges;
+        let is_broadcaster = badges.contains("broadcaster");
+        let is_moderator = is_broadcaster || tags.moderator || badges.contains("moderator");
+        let is_vip = is_moderator || tags.vip || badges.contains("vip");
+        let is_subscriber =
+            is_vip || tags.subscriber || badges.contains("subscriber") || badges.contains("founder");
+
+        match self {
+            Self::Everyone => true,
+            Self::Follower => is_subscriber || !tags.first_msg,
+            Self::Subscriber => is_subscriber,
+            Self::Vip => is_vip,
+            Self::Moderator => is_moderator,
+            Self::Broadcaster => is_broadcaster,
+            Self::Users(user_ids) =>
+                is_broadcaster ||
+                    tags.user_id.as_ref().is_some_and(|user_id| user_ids.contains(user_id)),
+        }
+    }
+}
+
+/// Time between two uses of a command, written as seconds (`30` or `"30"`)
+/// or with a unit (`"30s"`, `"5m"`, `"1h"`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Cooldown(pub Duration);
+
+impl FromStr for Cooldown {
+    type Err = String;
+
+    fn from_str(cooldown: &str) -> std::result::Result<Self, Self::Err> {
+        let cooldown = cooldown.trim();
+        let split = cooldown.find(|char: char| !char.is_ascii_digit()).unwrap_or(cooldown.len());
+        let (amount, unit) = cooldown.split_at(split);
+        let amount: u64 = amount.parse().map_err(|_| format!("Invalid cooldown: {:?}", cooldown))?;
+
+        let unit_seconds = match unit.trim() {
+            "" | "s" => 1,
+            "m" => 60,
+            "h" => 60 * 60,
+            _ => {
+                return Err(format!("Invalid cooldown unit in {:?}, expected s, m or h", cooldown));
+            }
+        };
+        amount
+            .checked_mul(unit_seconds)
+            .map(|seconds| Self(Duration::from_secs(seconds)))
+            .ok_or_else(|| format!("Cooldown too long: {:?}", cooldown))
+    }
+}
+
+impl Display for Cooldown {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}s", self.0.as_secs())
+    }
+}
+
+impl Serialize for Cooldown {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Cooldown {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum RawCooldown {
+            Seconds(u64),
+            Text(String),
+        }
+
+        match RawCooldown::deserialize(deserializer)? {
+            RawCooldown::Seconds(seconds) => Ok(Self(Duration::from_secs(seconds))),
+            RawCooldown::Text(text) => text.parse().map_err(de::Error::custom),
+        }
+    }
+}
+
+/// Who a cooldown applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CooldownScope {
+    /// Each user waits for their own cooldown in each channel.
+    User,
+    /// One use blocks the command for everyone in that channel.
+    Channel,
+    /// One use blocks the command for everyone in every channel.
+    Global,
+}
+
+/// Cooldowns of a command, every one that is set has to be over.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cooldowns {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub global: Option<Cooldown>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel: Option<Cooldown>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<Cooldown>,
+}
+
+impl Cooldowns {
+    /// The configured cooldowns, broadest scope first.
+    pub fn iter(&self) -> impl Iterator<Item = (CooldownScope, Duration)> {
+        [
+            (CooldownScope::Global, self.global),
+            (CooldownScope::Channel, self.channel),
+            (CooldownScope::User, self.user),
+        ]
+            .into_iter()
+            .filter_map(|(scope, cooldown)| cooldown.map(|cooldown| (scope, cooldown.0)))
+    }
+
+    fn get_mut(&mut self, scope: CooldownScope) -> &mut Option<Cooldown> {
+        match scope {
+            CooldownScope::Global => &mut self.global,
+            CooldownScope::Channel => &mut self.channel,
+            CooldownScope::User => &mut self.user,
+        }
+    }
+}
+
+/// Whether moderators and the broadcaster may use a command during its cooldown.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CooldownBypass {
+    /// Everyone waits for the cooldown.
+    #[default]
+    Off,
+    /// The cooldown is skipped and restarted for everyone else.
+    Bypass,
+    /// The cooldown is skipped without touching it.
+    BypassKeepTimer,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "RawCommand")]
 pub struct Command {
     pub name: String,
+    /// Further words that trigger the command, sharing its cooldowns.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
     pub response: String,
-    pub cooldown_in_s: String,
-    pub cooldown_scope: String,
+    pub cooldowns: Cooldowns,
+    /// Overrides the file wide `cooldown_bypass` when set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cooldown_bypass: Option<CooldownBypass>,
+    /// Channels the command is available in, see [`enabled_in`].
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub channels: Vec<String>,
+    pub permission: Permission,
+    /// Sent to users who lack the permission, they are ignored when unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub denial_message: Option<String>,
+    /// Sent once per cooldown to users who trigger the command too early.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cooldown_message: Option<String>,
+}
+
+/// A command as written in the file, which may still use the single
+/// `cooldown`/`cooldown_scope` pair of older files.
+#[derive(Deserialize)]
+struct RawCommand {
+    name: String,
+    #[serde(default)]
+    aliases: Vec<String>,
+    response: String,
+    #[serde(default)]
+    cooldown: Option<Cooldown>,
+    /// Legacy name of `cooldown`, its `global` scope means everyone in one channel.
+    #[serde(default)]
+    cooldown_in_s: Option<Cooldown>,
+    #[serde(default)]
+    cooldown_scope: Option<CooldownScope>,
+    #[serde(default)]
+    cooldowns: Cooldowns,
+    #[serde(default)]
+    cooldown_bypass: Option<CooldownBypass>,
+    #[serde(default)]
+    channels: Vec<String>,
+    #[serde(default)]
+    permission: Permission,
+    #[serde(default)]
+    denial_message: Option<String>,
+    #[serde(default)]
+    cooldown_message: Option<String>,
+}
+
+impl TryFrom<RawCommand> for Command {
+    type Error = String;
+
+    fn try_from(raw: RawCommand) -> std::result::Result<Self, Self::Error> {
+        let mut cooldowns = raw.cooldowns;
+        let is_legacy = raw.cooldown_in_s.is_some();
+        let cooldown = match (raw.cooldown, raw.cooldown_in_s) {
+            (Some(_), Some(_)) => {
+                return Err(format!("cooldown and cooldown_in_s of {} are both set", raw.name));
+            }
+            (cooldown, legacy_cooldown) => cooldown.or(legacy_cooldown),
+        };
+        match (cooldown, raw.cooldown_scope) {
+            (Some(cooldown), Some(scope)) => {
+                // Files from before combined cooldowns used `global` for
+                // everyone in one channel, across channels needs `cooldowns.global`
+                let scope = match scope {
+                    CooldownScope::Global if is_legacy => {
+                        warn!(
+                            "Command {} uses cooldown_in_s with the global scope, applying it per channel",
+                            raw.name
+                        );
+                        CooldownScope::Channel
+                    }
+                    scope => scope,
+                };
+                let scoped = cooldowns.get_mut(scope);
+                if scoped.is_some() {
+                    return Err(format!("{:?} cooldown of {} is set twice", scope, raw.name));
+                }
+                *scoped = Some(cooldown);
+            }
+            (None, None) => {}
+            _ => {
+                return Err(format!("cooldown and cooldown_scope of {} must be set together", raw.name));
+            }
+        }
+
+        Ok(Self {
+            name: raw.name,
+            aliases: raw.aliases
+                .into_iter()
+                .map(|alias| alias.trim_start_matches('!').to_string())
+                .collect(),
+            response: raw.response,
+            cooldowns,
+            cooldown_bypass: raw.cooldown_bypass,
+            channels: raw.channels,
+            permission: raw.permission,
+            denial_message: raw.denial_message,
+            cooldown_message: raw.cooldown_message,
+        })
+    }
+}
+
+impl Command {
+    /// The word after `!` that triggers the command.
+    pub fn trigger(&self) -> &str {
+        self.name.split_whitespace().next().unwrap_or("")
+    }
+
+    /// Whether `word` is the trigger or one of the aliases.
+    pub fn is_triggered_by(&self, word: &str) -> bool {
+        self.trigger() == word || self.aliases.iter().any(|alias| alias == word)
+    }
+
+    pub fn is_enabled_in(&self, channel: &str) -> bool {
+        enabled_in(&self.channels, channel)
+    }
+
+    /// Whether both commands can be used in at least one common channel.
+    fn shares_channel_with(&self, other: &Command) -> bool {
+        self.channels.is_empty() ||
+            other.channels.is_empty() ||
+            self.channels.iter().any(|channel| other.is_enabled_in(channel))
+    }
+}
+
+/// Response to a USERNOTICE event such as a sub or raid.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventResponse {
+    /// The notice's `msg-id`, e.g. `resub` or `raid`.
+    pub event: String,
+    pub response: String,
+    /// Channels the response is sent in, see [`enabled_in`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub channels: Vec<String>,
+}
+
+impl EventResponse {
+    pub fn is_enabled_in(&self, channel: &str) -> bool {
+        enabled_in(&self.channels, channel)
+    }
+}
+
+/// Greeting for chatters writing their first message in a channel.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Welcome {
+    pub response: String,
+    /// Minimum time between two welcomes in the same channel.
+    #[serde(default, alias = "cooldown_in_s")]
+    pub cooldown: Cooldown,
+    /// Also welcome chatters the bot has not seen since it last joined the
+    /// channel, not only those writing there for the very first time. Twitch
+    /// does not announce stream starts, so restarts and reconnects count as
+    /// a new stream.
+    #[serde(default)]
+    pub first_in_stream: bool,
+    /// Channels the welcome is sent in, see [`enabled_in`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub channels: Vec<String>,
+}
+
+impl Welcome {
+    pub fn is_enabled_in(&self, channel: &str) -> bool {
+        enabled_in(&self.channels, channel)
+    }
+}
+
+/// The commands file is either a plain list of commands or an object with
+/// commands, event responses, welcomes and file wide settings.
+#[derive(Default, Deserialize)]
+struct CommandsFile {
+    commands: Vec<Command>,
+    #[serde(default)]
+    cooldown_bypass: CooldownBypass,
+    #[serde(default)]
+    events: Vec<EventResponse>,
+    #[serde(default)]
+    welcomes: Vec<Welcome>,
 }
 
 #[derive(Clone)]
-pub struct Commands(Vec<Command>);
+pub struct Commands {
+    commands: Vec<Command>,
+    cooldown_bypass: CooldownBypass,
+    events: Vec<EventResponse>,
+    welcomes: Vec<Welcome>,
+}
 
 impl Commands {
-    pub fn new(file_path: &str) -> Self {
-        let commands = read_commands_from_file(file_path);
+    pub fn new(file_path: &str) -> Result<Self> {
+        let CommandsFile { commands, cooldown_bypass, events, welcomes } =
+            read_commands_from_file(file_path)?;
 
-        for command in commands.clone() {
-            if let Err(error) = validate_command_placeholders(&command) {
-                panic!("{}", error);
-            };
+        for (index, command) in commands.iter().enumerate() {
+            validate_command_placeholders(command)?;
+            validate_aliases(command, &commands[..index])?;
+            if let Some(denial_message) = &command.denial_message {
+                validate_template_placeholders(&command.name, denial_message, &["sender"])?;
+            }
+            if let Some(cooldown_message) = &command.cooldown_message {
+                validate_template_placeholders(&command.name, cooldown_message, &[
+                    "sender",
+                    "remaining",
+                    "command",
+                ])?;
+            }
+        }
+        for event in &events {
+            let allowed = [&COMMON_PLACEHOLDERS[..], event_placeholders(&event.event)].concat();
+            validate_template_placeholders(&event.event, &event.response, &allowed)?;
+        }
+        for welcome in &welcomes {
+            validate_template_placeholders("welcome", &welcome.response, &["sender"])?;
         }
 
         info!("Validated and parsed commands");
-        Self(commands)
+        Ok(Self {
+            commands,
+            cooldown_bypass,
+            events,
+            welcomes,
+        })
     }
+
     pub fn get(&self) -> &Vec<Command> {
-        &self.0
+        &self.commands
+    }
+
+    /// The bypass that applies to `command`, its own or the file wide one.
+    pub fn cooldown_bypass(&self, command: &Command) -> CooldownBypass {
+        command.cooldown_bypass.unwrap_or(self.cooldown_bypass)
+    }
+
+    /// The response configured for the event kind in `channel`, if any.
+    pub fn event_response(&self, kind: &str, channel: &str) -> Option<&EventResponse> {
+        self.events.iter().find(|event| event.event == kind && event.is_enabled_in(channel))
+    }
+
+    /// The welcome configured for `channel`, if any.
+    pub fn welcome(&self, channel: &str) -> Option<&Welcome> {
+        self.welcomes.iter().find(|welcome| welcome.is_enabled_in(channel))
     }
 }
 
-fn read_commands_from_file(file_path: &str) -> Vec<Command> {
-    let Ok(file) = File::open(file_path) else {
-        panic!("Failed to open commands.json file");
-    };
+fn read_commands_from_file(file_path: &str) -> Result<CommandsFile> {
+    let file = File::open(file_path).map_err(|source| Error::ReadConfig {
+        path: file_path.into(),
+        source,
+    })?;
     let reader = BufReader::new(file);
-    let Ok(commands) = serde_json::from_reader(reader) else {
-        panic!("Failed to parse commands.json");
-    };
-
-    commands
+    // Decide on the format first so errors point at the offending field
+    let parsed = serde_json::from_reader(reader).and_then(|value: serde_json::Value| {
+        if value.is_array() {
+            serde_json::from_value(value).map(|commands| CommandsFile { commands, ..Default::default() })
+        } else {
+            serde_json::from_value(value)
+        }
+    });
+    parsed.map_err(|source| Error::ParseConfig {
+        path: file_path.into(),
+        source,
+    })
 }
 
-fn validate_command_placeholders(command: &Command) -> Result<(), String> {
+fn validate_command_placeholders(command: &Command) -> Result<()> {
     let re = Regex::new(r"\{(\w+)\}").unwrap();
 
     let name_placeholders: HashSet<_> = re
@@ -60,14 +449,54 @@ fn validate_command_placeholders(command: &Command) -> Result<(), String> {
         .collect();
 
     if name_placeholders != response_placeholders {
-        return Err(
-            format!(
-                "Placeholder mismatch in command '{}': {:?} (name) != {:?} (response)",
-                command.name,
+        return Err(Error::InvalidCommand {
+            name: command.name.clone(),
+            reason: format!(
+                "Placeholder mismatch: {:?} (name) != {:?} (response)",
                 name_placeholders,
                 response_placeholders
-            )
-        );
+            ),
+        });
+    }
+
+    Ok(())
+}
+
+/// Checks that no alias of `command` triggers one of the `previous` commands
+/// in a shared channel, and that none of their aliases triggers `command`.
+fn validate_aliases(command: &Command, previous: &[Command]) -> Result<()> {
+    for other in previous.iter().filter(|other| other.shares_channel_with(command)) {
+        let collision = command.aliases
+            .iter()
+            .find(|alias| other.is_triggered_by(alias))
+            .or_else(|| other.aliases.iter().find(|alias| command.is_triggered_by(alias)));
+
+        if let Some(alias) = collision {
+            return Err(Error::InvalidCommand {
+                name: command.name.clone(),
+                reason: format!("Alias {} collides with command {}", alias, other.name),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that a response template only uses the given placeholders.
+fn validate_template_placeholders(name: &str, template: &str, allowed: &[&str]) -> Result<()> {
+    let re = Regex::new(r"\{(\w+)\}").unwrap();
+
+    let unknown: Vec<_> = re
+        .captures_iter(template)
+        .filter_map(|cap| cap.get(1).map(|m| m.as_str()))
+        .filter(|placeholder| !allowed.contains(placeholder))
+        .collect();
+
+    if !unknown.is_empty() {
+        return Err(Error::InvalidCommand {
+            name: name.to_string(),
+            reason: format!("Unknown placeholders in {} response: {:?}", name, unknown),
+        });
     }
 
     Ok(())
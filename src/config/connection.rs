@@ -0,0 +1,18 @@
+/// Where and how the bot connects to the IRC server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionConfig {
+    pub host: String,
+    pub port: u16,
+    pub tls: bool,
+}
+
+impl Default for ConnectionConfig {
+    /// Twitch's IRC endpoint over TLS.
+    fn default() -> Self {
+        Self {
+            host: "irc.chat.twitch.tv".to_string(),
+            port: 6697,
+            tls: true,
+        }
+    }
+}
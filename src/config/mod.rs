@@ -1 +1,15 @@
 pub mod command_parser;
+pub mod connection;
+pub mod watcher;
+
+/// Normalizes a channel name to the lowercase form without the leading `#`.
+pub fn channel_name(channel: &str) -> String {
+    channel.trim().trim_start_matches('#').to_lowercase()
+}
+
+/// Whether a `channels` filter from the commands file includes `channel`,
+/// an empty filter includes every channel.
+pub fn enabled_in(channels: &[String], channel: &str) -> bool {
+    let channel = channel_name(channel);
+    channels.is_empty() || channels.iter().any(|enabled| channel_name(enabled) == channel)
+}
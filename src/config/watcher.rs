@@ -0,0 +1,58 @@
+use std::{ fs, sync::Arc, time::{ Duration, SystemTime } };
+
+use tokio::{ sync::watch, time::MissedTickBehavior };
+use tracing::{ error, info };
+
+use crate::{
+    config::command_parser::Commands,
+    messages::cooldown::CooldownTracker,
+};
+
+/// How often the commands file is checked for changes.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Reloads the commands file whenever it changes on disk. An invalid file is
+/// logged and the previous commands stay active until the file is fixed.
+pub fn watch_commands(
+    file_path: String,
+    commands: Commands,
+    cooldowns: CooldownTracker
+) -> watch::Receiver<Arc<Commands>> {
+    let (sender, receiver) = watch::channel(Arc::new(commands));
+
+    tokio::spawn(async move {
+        let mut last_modified = modified(&file_path);
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = sender.closed() => break,
+            }
+
+            let current_modified = modified(&file_path);
+            if current_modified == last_modified {
+                continue;
+            }
+            last_modified = current_modified;
+
+            match Commands::new(&file_path) {
+                Ok(new_commands) => {
+                    cooldowns.retain_unchanged(&sender.borrow(), &new_commands);
+                    sender.send_replace(Arc::new(new_commands));
+                    info!("Reloaded commands from {}", file_path);
+                }
+                Err(error) => error!("Keeping previous commands, reloading failed: {}", error),
+            }
+        }
+    });
+
+    receiver
+}
+
+fn modified(file_path: &str) -> Option<SystemTime> {
+    fs::metadata(file_path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
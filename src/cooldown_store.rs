@@ -0,0 +1,83 @@
+use std::{ fs, io, sync::Arc, time::Duration };
+
+use serde::{ Deserialize, Serialize };
+use tokio::{ sync::watch, time::MissedTickBehavior };
+use tracing::{ error, info, warn };
+
+use crate::{
+    config::command_parser::Commands,
+    messages::cooldown::{ CooldownKey, CooldownTracker },
+};
+
+/// How often the cooldown state is written to disk.
+const SNAPSHOT_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Serialize, Deserialize)]
+struct StoredTrigger {
+    key: CooldownKey,
+    triggered_at: u64,
+}
+
+/// Restores the cooldowns saved in `state_file` and keeps saving them there,
+/// so restarting the bot does not reset long cooldowns.
+pub fn persist_cooldowns(
+    state_file: String,
+    cooldowns: CooldownTracker,
+    commands: watch::Receiver<Arc<Commands>>
+) {
+    restore(&state_file, &cooldowns, &commands.borrow());
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SNAPSHOT_INTERVAL);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        loop {
+            interval.tick().await;
+            let commands = commands.borrow().clone();
+            if let Err(error) = save(&state_file, &cooldowns, &commands) {
+                error!("Saving cooldowns to {} failed: {}", state_file, error);
+            }
+        }
+    });
+}
+
+/// Writes the cooldowns that are still running to `state_file`.
+pub fn save(state_file: &str, cooldowns: &CooldownTracker, commands: &Commands) -> io::Result<()> {
+    cooldowns.evict_expired(commands);
+    let stored: Vec<StoredTrigger> = cooldowns
+        .snapshot()
+        .into_iter()
+        .map(|(key, triggered_at)| StoredTrigger { key, triggered_at })
+        .collect();
+
+    // Write to a temporary file first so a crash never leaves half a snapshot
+    let temporary_file = format!("{}.tmp", state_file);
+    fs::write(&temporary_file, serde_json::to_vec(&stored)?)?;
+    fs::rename(&temporary_file, state_file)
+}
+
+fn restore(state_file: &str, cooldowns: &CooldownTracker, commands: &Commands) {
+    let stored: Vec<StoredTrigger> = match fs::read(state_file) {
+        Ok(contents) =>
+            match serde_json::from_slice(&contents) {
+                Ok(stored) => stored,
+                Err(error) => {
+                    warn!("Ignoring unreadable cooldowns in {}: {}", state_file, error);
+                    return;
+                }
+            }
+        Err(error) if error.kind() == io::ErrorKind::NotFound => {
+            return;
+        }
+        Err(error) => {
+            warn!("Reading cooldowns from {} failed: {}", state_file, error);
+            return;
+        }
+    };
+
+    let triggers = stored
+        .into_iter()
+        .map(|trigger| (trigger.key, trigger.triggered_at))
+        .collect();
+    let restored = cooldowns.restore(triggers, commands);
+    info!("Restored {} running cooldowns from {}", restored, state_file);
+}
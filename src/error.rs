@@ -0,0 +1,48 @@
+use std::path::PathBuf;
+
+use thiserror::Error;
+use tokio::sync::mpsc::error::SendError;
+
+use crate::tcp_handler::BotMessage;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Failed to open commands file {}: {source}", path.display())]
+    ReadConfig {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Failed to parse commands file {}: {source}", path.display())]
+    ParseConfig {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    #[error("Invalid command '{name}': {reason}")]
+    InvalidCommand {
+        name: String,
+        reason: String,
+    },
+    #[error(transparent)]
+    Connect(#[from] ConnectError),
+    #[error("Failed to parse message {raw:?}: {reason}")]
+    ParseMessage {
+        raw: String,
+        reason: String,
+    },
+    #[error("Sending to tcp_handler failed: {0}")]
+    Send(#[from] SendError<BotMessage>),
+}
+
+#[derive(Debug, Error)]
+pub enum ConnectError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    /// The server rejected the OAuth token, retrying will not help.
+    #[error("Authentication failed: {0}")]
+    AuthenticationFailed(String),
+    /// The chat bot went away while logging in, there is nobody to connect for.
+    #[error("Chat bot closed during login")]
+    BotClosed,
+}
@@ -2,3 +2,6 @@ pub mod tcp_handler;
 pub mod chat_bot;
 pub mod messages;
 pub mod config;
+pub mod error;
+pub mod rate_limiter;
+pub mod cooldown_store;
@@ -4,7 +4,11 @@
 
 use dotenv::dotenv;
 
-use cb_twitchchatbot_rust::chat_bot::ChatBot;
+use cb_twitchchatbot_rust::{
+    chat_bot::ChatBot,
+    config::connection::ConnectionConfig,
+    rate_limiter::OverflowPolicy,
+};
 use tracing::Level;
 use tracing_subscriber::FmtSubscriber;
 
@@ -22,12 +26,49 @@ async fn main() {
 
     tracing::subscriber::set_global_default(subscriber).expect("setting default subscriber failed");
 
+    let default_connection = ConnectionConfig::default();
+    let connection = ConnectionConfig {
+        host: std::env::var("IRC_HOST").unwrap_or(default_connection.host),
+        port: std::env::var("IRC_PORT").map_or(default_connection.port, |port| {
+            port.parse().expect("IRC_PORT env var invalid")
+        }),
+        tls: std::env::var("IRC_TLS").map_or(default_connection.tls, |tls| {
+            tls.parse().expect("IRC_TLS env var invalid, expected true or false")
+        }),
+    };
+
     let nickname = std::env::var("NICKNAME").expect("NICKNAME env var not set");
     let oauth_token = std::env::var("OAUTH_TOKEN").expect("OAUTH_TOKEN env var not set");
-    let channel = std::env::var("CHANNEL").expect("CHANNEL env var not set");
+    let channels = std::env::var("CHANNELS")
+        .or_else(|_| std::env::var("CHANNEL"))
+        .expect("CHANNELS env var not set");
+    let channels = channels
+        .split(',')
+        .map(str::trim)
+        .filter(|channel| !channel.is_empty())
+        .map(String::from)
+        .collect();
     let file_path = std::env::var("FILEPATH").expect("FILEPATH env var not set");
+    let overflow_policy = std::env::var("RATE_LIMIT_POLICY").map_or_else(
+        |_| OverflowPolicy::default(),
+        |policy| policy.parse().expect("RATE_LIMIT_POLICY env var invalid")
+    );
 
-    let mut bot = ChatBot::new(nickname, oauth_token, channel, file_path);
+    let mut bot = match
+        ChatBot::new(connection, nickname, oauth_token, channels, file_path, overflow_policy)
+    {
+        Ok(bot) => bot,
+        Err(error) => {
+            tracing::error!("Starting the bot failed: {error}");
+            std::process::exit(1);
+        }
+    };
+    if let Ok(state_file) = std::env::var("COOLDOWN_STATE_FILE") {
+        bot.persist_cooldowns(&state_file);
+    }
 
-    bot.run().await;
+    if let Err(error) = bot.run().await {
+        tracing::error!("Stopping the bot: {error}");
+        std::process::exit(1);
+    }
 }
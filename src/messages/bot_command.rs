@@ -1,11 +1,18 @@
-use std::{ collections::HashMap, sync::{ Arc, Mutex }, time::{ SystemTime, UNIX_EPOCH } };
+use std::time::Duration;
 
 use regex::Regex;
 use serde::{ Deserialize, Serialize };
 use tracing::{ error, info, warn };
 
-use crate::{ config::command_parser::Commands, messages::private_message::PrivateMessageResponse };
-use crate::config::command_parser::Command;
+use crate::{
+    config::command_parser::{ Command, Commands, CooldownBypass, Permission },
+    messages::{
+        cooldown::{ CooldownKey, CooldownTracker, DENIAL_COOLDOWN, NOTICE_COOLDOWN },
+        private_message::PrivateMessageResponse,
+        tags::PrivmsgTags,
+    },
+};
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct BotCommand {
     pub command: String,
@@ -15,12 +22,20 @@ pub struct BotCommand {
 impl BotCommand {
     pub fn parse(
         &self,
-        display_name: &str,
+        tags: &PrivmsgTags,
         channel: &str,
-        commands: Commands,
-        last_triggers: Arc<Mutex<HashMap<String, HashMap<String, u64>>>>
+        commands: &Commands,
+        cooldowns: &CooldownTracker
     ) -> Option<PrivateMessageResponse> {
-        let command = match self.find_command(&commands) {
+        let Some(display_name) = tags.display_name.as_deref() else {
+            error!("No display_name for command {}", self.command);
+            return None;
+        };
+        // Display names can be renamed or change casing, the id stays the same
+        let lowercase_name = display_name.to_lowercase();
+        let user_id = tags.user_id.as_deref().unwrap_or(&lowercase_name);
+
+        let command = match self.find_command(commands, channel) {
             Some(cmd) => cmd,
             None => {
                 warn!("Command {} not found", self.command);
@@ -28,10 +43,37 @@ impl BotCommand {
             } // Command not found
         };
 
+        if !command.permission.allows(tags) {
+            info!("User: {} is not permitted to use {} command", display_name, self.command);
+            return self.denial(&command, (display_name, user_id), channel, cooldowns);
+        }
+
         let response_message = self.replace_sender(&command.response, display_name);
 
-        if !self.check_cooldown(&command, display_name, &last_triggers) {
-            return None; // Command is under cooldown
+        let bypass = if Permission::Moderator.allows(tags) {
+            commands.cooldown_bypass(&command)
+        } else {
+            CooldownBypass::Off
+        };
+        if
+            let Err((blocking_key, remaining)) = self.check_cooldown(
+                &command,
+                display_name,
+                user_id,
+                channel,
+                cooldowns,
+                bypass
+            )
+        {
+            // Command is under cooldown
+            return self.cooldown_notice(
+                &command,
+                &blocking_key,
+                remaining,
+                (display_name, user_id),
+                channel,
+                cooldowns
+            );
         }
 
         let response_message = self.replace_placeholders(
@@ -49,13 +91,12 @@ impl BotCommand {
         Some(PrivateMessageResponse::from(channel, &response_message))
     }
 
-    fn find_command(&self, commands: &Commands) -> Option<Command> {
+    fn find_command(&self, commands: &Commands, channel: &str) -> Option<Command> {
         let command = commands
             .get()
             .iter()
             .find(|command| {
-                let cmd_name = command.name.split_whitespace().next().unwrap_or("");
-                cmd_name == self.command
+                command.is_triggered_by(&self.command) && command.is_enabled_in(channel)
             });
         command.cloned()
     }
@@ -68,53 +109,91 @@ impl BotCommand {
         &self,
         command: &Command,
         display_name: &str,
-        last_triggers: &Arc<Mutex<HashMap<String, HashMap<String, u64>>>>
-    ) -> bool {
-        let username = display_name.to_string();
-        let cooldown_scope = &command.cooldown_scope;
-        let cooldown = command.cooldown_in_s.parse().unwrap_or(0);
-        let current_time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
-
-        let mut last_triggers = last_triggers.lock().expect("Failed to lock last_triggers");
-
-        match cooldown_scope.as_str() {
-            "user" => {
-                let user_cooldown = last_triggers.entry(self.command.clone()).or_default();
-
-                if let Some(last_trigger) = user_cooldown.get(&username) {
-                    if current_time - *last_trigger < cooldown {
-                        info!(
-                            "User: {} is still under cooldown for {} command",
-                            display_name,
-                            self.command
-                        );
-                        return false;
-                    }
-                }
-
-                user_cooldown.insert(username, current_time);
+        user_id: &str,
+        channel: &str,
+        cooldowns: &CooldownTracker,
+        bypass: CooldownBypass
+    ) -> std::result::Result<(), (CooldownKey, Duration)> {
+        if bypass != CooldownBypass::Off {
+            info!("User: {} bypassed the cooldown for {} command", display_name, self.command);
+            if bypass == CooldownBypass::BypassKeepTimer {
+                return Ok(());
             }
-            "global" => {
-                if let Some(last_trigger_time) = last_triggers.get(&self.command) {
-                    if let Some(last_trigger) = last_trigger_time.get("global") {
-                        if current_time - *last_trigger < cooldown {
-                            info!("Command {} is still under global cooldown", self.command);
-                            return false;
-                        }
-                    }
-                }
+        }
 
-                let mut cooldowns = HashMap::new();
-                cooldowns.insert("global".to_string(), current_time);
-                last_triggers.insert(self.command.clone(), cooldowns);
-            }
-            _ => {
-                error!("Invalid cooldown_scope: {}", cooldown_scope);
-                return false;
-            }
+        let command_cooldowns = command.cooldowns
+            .iter()
+            .map(|(scope, cooldown)| {
+                (CooldownKey::new(scope, command.trigger(), channel, user_id), cooldown)
+            })
+            .collect();
+
+        cooldowns.trigger(command_cooldowns, bypass == CooldownBypass::Off).inspect_err(|(key, _)| {
+            info!(
+                "User: {} is still under {:?} cooldown for {} command",
+                display_name,
+                key.scope(),
+                self.command
+            );
+        })
+    }
+
+    /// Tells the user they may not use the command, at most once per
+    /// [`DENIAL_COOLDOWN`] and sharing the channel's notice cooldown so
+    /// denials cannot be used to spam the chat.
+    fn denial(
+        &self,
+        command: &Command,
+        (display_name, user_id): (&str, &str),
+        channel: &str,
+        cooldowns: &CooldownTracker
+    ) -> Option<PrivateMessageResponse> {
+        let denial_message = command.denial_message.as_ref()?;
+        let throttles = vec![
+            (CooldownKey::denial(command.trigger(), channel, user_id), DENIAL_COOLDOWN),
+            (CooldownKey::channel_notice(command.trigger(), channel), NOTICE_COOLDOWN)
+        ];
+        if cooldowns.trigger(throttles, true).is_err() {
+            info!(
+                "User: {} or the channel was recently told they may not use {} command",
+                display_name,
+                self.command
+            );
+            return None;
+        }
+
+        Some(PrivateMessageResponse::from(channel, &self.replace_sender(denial_message, display_name)))
+    }
+
+    /// Tells the user how long the command is cooling down, at most once
+    /// for each time the blocking cooldown was started and once per
+    /// [`NOTICE_COOLDOWN`] in the channel.
+    fn cooldown_notice(
+        &self,
+        command: &Command,
+        blocking_key: &CooldownKey,
+        remaining: Duration,
+        (display_name, user_id): (&str, &str),
+        channel: &str,
+        cooldowns: &CooldownTracker
+    ) -> Option<PrivateMessageResponse> {
+        let cooldown_message = command.cooldown_message.as_ref()?;
+        let notice_key = CooldownKey::notice(command.trigger(), channel, user_id);
+        let channel_notice_key = CooldownKey::channel_notice(command.trigger(), channel);
+        if !cooldowns.notice(notice_key, channel_notice_key, blocking_key) {
+            info!(
+                "User: {} or the channel was already told about the cooldown of {} command",
+                display_name,
+                self.command
+            );
+            return None;
         }
 
-        true
+        let response_message = self
+            .replace_sender(cooldown_message, display_name)
+            .replace("{remaining}", &format_remaining(remaining))
+            .replace("{command}", &self.command);
+        Some(PrivateMessageResponse::from(channel, &response_message))
     }
 
     fn replace_placeholders(
@@ -151,3 +230,16 @@ impl BotCommand {
         }
     }
 }
+
+/// Formats a remaining cooldown like `45s` or `4m 5s`, rounded up so a
+/// running cooldown never reads `0s`.
+pub fn format_remaining(remaining: Duration) -> String {
+    let seconds = remaining.as_secs() + u64::from(remaining.subsec_nanos() > 0);
+    if seconds < 60 {
+        format!("{}s", seconds)
+    } else if seconds.is_multiple_of(60) {
+        format!("{}m", seconds / 60)
+    } else {
+        format!("{}m {}s", seconds / 60, seconds % 60)
+    }
+}
@@ -0,0 +1,315 @@
+use std::{
+    collections::HashMap,
+    sync::{ Arc, Mutex, MutexGuard },
+    time::{ Duration, SystemTime, UNIX_EPOCH },
+};
+
+use serde::{ Deserialize, Serialize };
+use tokio::time::Instant;
+
+use crate::config::{ channel_name, command_parser::{ Command, Commands, CooldownScope } };
+
+/// How long a user is not told again that they may not use a command.
+pub const DENIAL_COOLDOWN: Duration = Duration::from_secs(300);
+/// Minimum time between two cooldown or denial notices for the same command
+/// in one channel, however many users try it.
+pub const NOTICE_COOLDOWN: Duration = Duration::from_secs(30);
+
+/// What a recorded trigger blocks, depending on the cooldown's scope.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum CooldownKey {
+    Global {
+        command: String,
+    },
+    Channel {
+        channel: String,
+        command: String,
+    },
+    /// Users are identified by their `user-id`, which survives renames.
+    User {
+        channel: String,
+        command: String,
+        user_id: String,
+    },
+    /// When the user was last told about a running cooldown of the command.
+    Notice {
+        channel: String,
+        command: String,
+        user_id: String,
+    },
+    /// When anyone in the channel was last told about the command's
+    /// cooldown or that they may not use it.
+    ChannelNotice {
+        channel: String,
+        command: String,
+    },
+    /// When the user was last told they may not use the command.
+    Denial {
+        channel: String,
+        command: String,
+        user_id: String,
+    },
+}
+
+impl CooldownKey {
+    pub fn new(scope: CooldownScope, command: &str, channel: &str, user_id: &str) -> Self {
+        let command = command.to_string();
+        match scope {
+            CooldownScope::Global => Self::Global { command },
+            CooldownScope::Channel => Self::Channel { channel: channel_name(channel), command },
+            CooldownScope::User =>
+                Self::User {
+                    channel: channel_name(channel),
+                    command,
+                    user_id: user_id.to_string(),
+                },
+        }
+    }
+
+    pub fn notice(command: &str, channel: &str, user_id: &str) -> Self {
+        Self::Notice {
+            channel: channel_name(channel),
+            command: command.to_string(),
+            user_id: user_id.to_string(),
+        }
+    }
+
+    pub fn channel_notice(command: &str, channel: &str) -> Self {
+        Self::ChannelNotice {
+            channel: channel_name(channel),
+            command: command.to_string(),
+        }
+    }
+
+    pub fn denial(command: &str, channel: &str, user_id: &str) -> Self {
+        Self::Denial {
+            channel: channel_name(channel),
+            command: command.to_string(),
+            user_id: user_id.to_string(),
+        }
+    }
+
+    pub fn command(&self) -> &str {
+        match self {
+            | Self::Global { command }
+            | Self::Channel { command, .. }
+            | Self::User { command, .. }
+            | Self::Notice { command, .. }
+            | Self::ChannelNotice { command, .. }
+            | Self::Denial { command, .. } => command,
+        }
+    }
+
+    /// The cooldown scope the key belongs to, `None` for notices and denials.
+    pub fn scope(&self) -> Option<CooldownScope> {
+        match self {
+            Self::Global { .. } => Some(CooldownScope::Global),
+            Self::Channel { .. } => Some(CooldownScope::Channel),
+            Self::User { .. } => Some(CooldownScope::User),
+            Self::Notice { .. } | Self::ChannelNotice { .. } | Self::Denial { .. } => None,
+        }
+    }
+}
+
+/// Seconds since the Unix epoch, the unit saved cooldowns are stored in.
+pub fn current_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since_epoch| since_epoch.as_secs())
+}
+
+/// Source of the current time for cooldowns.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+}
+
+/// Monotonic clock of the tokio runtime, which follows paused time in tests.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokioClock;
+
+impl Clock for TokioClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Last trigger times of every running cooldown, shared between the bot and
+/// the tasks that reload and persist them.
+#[derive(Clone)]
+pub struct CooldownTracker {
+    last_triggers: Arc<Mutex<HashMap<CooldownKey, Instant>>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Default for CooldownTracker {
+    fn default() -> Self {
+        Self::with_clock(Arc::new(TokioClock))
+    }
+}
+
+impl CooldownTracker {
+    pub fn with_clock(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            last_triggers: Arc::new(Mutex::new(HashMap::new())),
+            clock,
+        }
+    }
+
+    /// The clock the cooldowns are measured with.
+    pub fn clock(&self) -> Arc<dyn Clock> {
+        self.clock.clone()
+    }
+
+    /// Records a use of the cooldowns if none of them is running, otherwise
+    /// returns the first running one with its remaining time. Unenforced
+    /// cooldowns are restarted without being checked.
+    pub fn trigger(
+        &self,
+        cooldowns: Vec<(CooldownKey, Duration)>,
+        enforce: bool
+    ) -> Result<(), (CooldownKey, Duration)> {
+        let now = self.clock.now();
+        let mut last_triggers = self.lock();
+
+        // Check every scope before recording any, a rejected use must not
+        // restart the cooldowns that did pass
+        if enforce {
+            for (key, cooldown) in &cooldowns {
+                if let Some(last_trigger) = last_triggers.get(key) {
+                    let elapsed = now.saturating_duration_since(*last_trigger);
+                    if elapsed < *cooldown {
+                        return Err((key.clone(), *cooldown - elapsed));
+                    }
+                }
+            }
+        }
+
+        for (key, _) in cooldowns {
+            last_triggers.insert(key, now);
+        }
+        Ok(())
+    }
+
+    /// Whether the user should be told about the running `blocking_key`
+    /// cooldown, which happens once each time it is started and at most once
+    /// per [`NOTICE_COOLDOWN`] for the command in the channel.
+    pub fn notice(
+        &self,
+        notice_key: CooldownKey,
+        channel_notice_key: CooldownKey,
+        blocking_key: &CooldownKey
+    ) -> bool {
+        let now = self.clock.now();
+        let mut last_triggers = self.lock();
+        let Some(blocked_since) = last_triggers.get(blocking_key).copied() else {
+            return false;
+        };
+        if last_triggers.get(&notice_key).is_some_and(|noticed| *noticed >= blocked_since) {
+            return false;
+        }
+        if
+            last_triggers
+                .get(&channel_notice_key)
+                .is_some_and(|noticed| now.saturating_duration_since(*noticed) < NOTICE_COOLDOWN)
+        {
+            return false;
+        }
+        last_triggers.insert(notice_key, now);
+        last_triggers.insert(channel_notice_key, now);
+        true
+    }
+
+    /// Drops the cooldowns of commands that were removed or changed by a reload.
+    pub fn retain_unchanged(&self, old_commands: &Commands, new_commands: &Commands) {
+        let commands_for = |commands: &Commands, trigger: &str| -> Vec<Command> {
+            commands
+                .get()
+                .iter()
+                .filter(|command| command.trigger() == trigger)
+                .cloned()
+                .collect()
+        };
+
+        self.lock().retain(|key, _| {
+            let old_command = commands_for(old_commands, key.command());
+            !old_command.is_empty() && old_command == commands_for(new_commands, key.command())
+        });
+    }
+
+    /// Drops cooldowns that ran out.
+    pub fn evict_expired(&self, commands: &Commands) {
+        let now = self.clock.now();
+        prune_expired(&mut self.lock(), commands, |triggered_at| {
+            now.saturating_duration_since(*triggered_at)
+        });
+    }
+
+    /// Running cooldowns with the Unix time in seconds they were started.
+    pub fn snapshot(&self) -> Vec<(CooldownKey, u64)> {
+        let now = self.clock.now();
+        let unix_now = current_time();
+        self.lock()
+            .iter()
+            .map(|(key, triggered_at)| {
+                let age = now.saturating_duration_since(*triggered_at).as_secs();
+                (key.clone(), unix_now.saturating_sub(age))
+            })
+            .collect()
+    }
+
+    /// Adds cooldowns saved by [`Self::snapshot`], skipping the ones that ran
+    /// out since. Returns how many were restored.
+    pub fn restore(&self, triggers: Vec<(CooldownKey, u64)>, commands: &Commands) -> usize {
+        let unix_now = current_time();
+        let mut restored: HashMap<CooldownKey, u64> = triggers.into_iter().collect();
+        prune_expired(&mut restored, commands, |triggered_at| {
+            Duration::from_secs(unix_now.saturating_sub(*triggered_at))
+        });
+
+        let now = self.clock.now();
+        let count = restored.len();
+        self.lock().extend(
+            restored.into_iter().map(|(key, triggered_at)| {
+                let age = Duration::from_secs(unix_now.saturating_sub(triggered_at));
+                (key, now.checked_sub(age).unwrap_or(now))
+            })
+        );
+        count
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<CooldownKey, Instant>> {
+        self.last_triggers.lock().expect("Failed to lock last_triggers")
+    }
+}
+
+/// Drops triggers whose cooldown ran out or whose command no longer exists.
+/// Notices live as long as the longest cooldown of their command, denials
+/// and channel notices for their fixed cooldown.
+fn prune_expired<T>(
+    last_triggers: &mut HashMap<CooldownKey, T>,
+    commands: &Commands,
+    age: impl Fn(&T) -> Duration
+) {
+    last_triggers.retain(|key, triggered_at| {
+        let mut matching = commands
+            .get()
+            .iter()
+            .filter(|command| command.trigger() == key.command())
+            .peekable();
+        if matching.peek().is_none() {
+            return false;
+        }
+        let cooldowns = matching.flat_map(|command| command.cooldowns.iter());
+        let longest = match (key, key.scope()) {
+            (CooldownKey::Denial { .. }, _) => Some(DENIAL_COOLDOWN),
+            (CooldownKey::ChannelNotice { .. }, _) => Some(NOTICE_COOLDOWN),
+            (_, Some(key_scope)) =>
+                cooldowns
+                    .filter(|(scope, _)| *scope == key_scope)
+                    .map(|(_, cooldown)| cooldown)
+                    .max(),
+            (_, None) => cooldowns.map(|(_, cooldown)| cooldown).max(),
+        };
+        longest.is_some_and(|cooldown| age(triggered_at) < cooldown)
+    });
+}
@@ -0,0 +1,223 @@
+use std::{ borrow::Cow, fmt::Display, str::FromStr };
+
+use crate::{
+    error::{ Error, Result },
+    messages::tags::{ escape_tag_value, unescape_tag_value },
+};
+
+/// A single IRC line split according to RFC 1459 and the IRCv3 message-tags spec.
+///
+/// Tag values are stored unescaped, the last parameter includes the trailing
+/// parameter (the part after ` :`) if there is one.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IrcMessage {
+    pub tags: Vec<(String, String)>,
+    pub prefix: Option<String>,
+    pub command: String,
+    pub params: Vec<String>,
+}
+
+impl IrcMessage {
+    pub fn new(command: &str, params: &[&str]) -> Self {
+        Self {
+            command: command.to_string(),
+            params: params
+                .iter()
+                .map(|param| param.to_string())
+                .collect(),
+            ..Self::default()
+        }
+    }
+
+    pub fn parse(line: &str) -> Result<Self> {
+        IrcMessageRef::parse(line).map(|message| message.to_owned_message())
+    }
+
+    /// Value of the last tag with the given key, like [`IrcMessageRef::tag`].
+    pub fn tag(&self, key: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .rfind(|(tag_key, _)| tag_key == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn param(&self, index: usize) -> Option<&str> {
+        self.params.get(index).map(String::as_str)
+    }
+}
+
+impl FromStr for IrcMessage {
+    type Err = Error;
+
+    fn from_str(line: &str) -> Result<Self> {
+        Self::parse(line)
+    }
+}
+
+impl Display for IrcMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if !self.tags.is_empty() {
+            let tags: Vec<String> = self.tags
+                .iter()
+                .map(|(key, value)| {
+                    if value.is_empty() {
+                        key.clone()
+                    } else {
+                        format!("{}={}", key, escape_tag_value(value))
+                    }
+                })
+                .collect();
+            write!(f, "@{} ", tags.join(";"))?;
+        }
+        if let Some(prefix) = &self.prefix {
+            write!(f, ":{} ", prefix)?;
+        }
+        write!(f, "{}", self.command)?;
+
+        for (index, param) in self.params.iter().enumerate() {
+            let is_last = index + 1 == self.params.len();
+            if is_last && (param.is_empty() || param.contains(' ') || param.starts_with(':')) {
+                write!(f, " :{}", param)?;
+            } else {
+                write!(f, " {}", param)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Borrowed view of an IRC line that only splits out the tags, prefix and
+/// command up front. Tags and parameters are parsed lazily on access and
+/// nothing is allocated unless a tag value needs unescaping.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IrcMessageRef<'a> {
+    raw_tags: &'a str,
+    prefix: Option<&'a str>,
+    command: &'a str,
+    raw_params: &'a str,
+}
+
+impl<'a> IrcMessageRef<'a> {
+    pub fn parse(line: &'a str) -> Result<Self> {
+        let mut rest = line.trim_end_matches(['\r', '\n']);
+
+        let mut raw_tags = "";
+        if let Some(tagged) = rest.strip_prefix('@') {
+            (raw_tags, rest) = tagged.split_once(' ').unwrap_or((tagged, ""));
+        }
+        rest = rest.trim_start_matches(' ');
+
+        let mut prefix = None;
+        if let Some(prefixed) = rest.strip_prefix(':') {
+            let (raw_prefix, remainder) = prefixed.split_once(' ').unwrap_or((prefixed, ""));
+            prefix = Some(raw_prefix);
+            rest = remainder.trim_start_matches(' ');
+        }
+
+        let (command, raw_params) = rest.split_once(' ').unwrap_or((rest, ""));
+        // Commands are words or three digit numerics, anything else would not
+        // survive being written back out
+        if command.is_empty() || !command.chars().all(|char| char.is_ascii_alphanumeric()) {
+            return Err(Error::ParseMessage {
+                raw: line.to_string(),
+                reason: format!("Invalid command {:?}", command),
+            });
+        }
+
+        Ok(Self {
+            raw_tags,
+            prefix,
+            command,
+            raw_params,
+        })
+    }
+
+    pub fn prefix(&self) -> Option<&'a str> {
+        self.prefix
+    }
+
+    pub fn command(&self) -> &'a str {
+        self.command
+    }
+
+    /// Tag keys with their unescaped values.
+    pub fn tags(&self) -> impl Iterator<Item = (&'a str, Cow<'a, str>)> {
+        self.raw_tags
+            .split(';')
+            .map(|tag| tag.split_once('=').unwrap_or((tag, "")))
+            .filter(|(key, _)| !key.is_empty())
+            .map(|(key, value)| {
+                let value = if value.contains('\\') {
+                    Cow::Owned(unescape_tag_value(value))
+                } else {
+                    Cow::Borrowed(value)
+                };
+                (key, value)
+            })
+    }
+
+    /// Value of the last tag with the given key.
+    pub fn tag(&self, key: &str) -> Option<Cow<'a, str>> {
+        self.tags()
+            .filter(|(tag_key, _)| *tag_key == key)
+            .last()
+            .map(|(_, value)| value)
+    }
+
+    pub fn params(&self) -> Params<'a> {
+        Params { rest: self.raw_params }
+    }
+
+    pub fn param(&self, index: usize) -> Option<&'a str> {
+        self.params().nth(index)
+    }
+
+    /// The last parameter, which holds the text for most commands.
+    pub fn last_param(&self) -> Option<&'a str> {
+        self.params().last()
+    }
+
+    /// Copies the message for handlers that need to keep it around.
+    pub fn to_owned_message(&self) -> IrcMessage {
+        IrcMessage {
+            tags: self
+                .tags()
+                .map(|(key, value)| (key.to_string(), value.into_owned()))
+                .collect(),
+            prefix: self.prefix.map(String::from),
+            command: self.command.to_string(),
+            params: self.params().map(String::from).collect(),
+        }
+    }
+}
+
+impl From<IrcMessageRef<'_>> for IrcMessage {
+    fn from(message: IrcMessageRef<'_>) -> Self {
+        message.to_owned_message()
+    }
+}
+
+/// Iterator over the parameters of an [`IrcMessageRef`].
+#[derive(Debug, Clone)]
+pub struct Params<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Iterator for Params<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let rest = self.rest.trim_start_matches(' ');
+        if rest.is_empty() {
+            self.rest = rest;
+            return None;
+        }
+        if let Some(trailing) = rest.strip_prefix(':') {
+            self.rest = "";
+            return Some(trailing);
+        }
+        let (param, remainder) = rest.split_once(' ').unwrap_or((rest, ""));
+        self.rest = remainder;
+        Some(param)
+    }
+}
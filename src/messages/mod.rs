@@ -1,2 +1,7 @@
 pub mod private_message;
 pub mod bot_command;
+pub mod irc_message;
+pub mod tags;
+pub mod user_notice;
+pub mod welcome;
+pub mod cooldown;
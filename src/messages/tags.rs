@@ -0,0 +1,392 @@
+use std::{ collections::HashMap, str::FromStr };
+
+use serde::{ Deserialize, Serialize };
+
+/// Decodes a tag value according to the IRCv3 message-tags escaping rules.
+pub fn unescape_tag_value(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(char) = chars.next() {
+        if char != '\\' {
+            unescaped.push(char);
+            continue;
+        }
+        // An invalid escape drops the backslash, a trailing one is removed
+        match chars.next() {
+            Some(':') => unescaped.push(';'),
+            Some('s') => unescaped.push(' '),
+            Some('r') => unescaped.push('\r'),
+            Some('n') => unescaped.push('\n'),
+            Some(other) => unescaped.push(other),
+            None => {}
+        }
+    }
+    unescaped
+}
+
+/// Encodes a tag value according to the IRCv3 message-tags escaping rules.
+pub fn escape_tag_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for char in value.chars() {
+        match char {
+            ';' => escaped.push_str("\\:"),
+            ' ' => escaped.push_str("\\s"),
+            '\\' => escaped.push_str("\\\\"),
+            '\r' => escaped.push_str("\\r"),
+            '\n' => escaped.push_str("\\n"),
+            other => escaped.push(other),
+        }
+    }
+    escaped
+}
+
+/// Badge name to version, e.g. `subscriber` -> `12`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Badges(HashMap<String, String>);
+
+impl Badges {
+    fn parse(value: &str) -> Self {
+        Self(
+            value
+                .split(',')
+                .filter_map(|badge| badge.split_once('/'))
+                .map(|(name, version)| (name.to_string(), version.to_string()))
+                .collect()
+        )
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.0.contains_key(name)
+    }
+
+    pub fn version(&self, name: &str) -> Option<&str> {
+        self.0.get(name).map(String::as_str)
+    }
+
+    /// Whether the badges mark the user as broadcaster, moderator or VIP.
+    pub fn is_privileged(&self) -> bool {
+        ["broadcaster", "moderator", "vip"].iter().any(|badge| self.contains(badge))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct EmotePosition {
+    pub start_position: usize,
+    pub end_position: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Emote {
+    pub id: String,
+    pub positions: Vec<EmotePosition>,
+}
+
+fn parse_emotes(value: &str) -> Result<Vec<Emote>, String> {
+    value
+        .split('/')
+        .filter(|emote| !emote.is_empty())
+        .map(|emote| {
+            let (id, positions) = emote.split_once(':').unwrap_or((emote, ""));
+            let positions = positions
+                .split(',')
+                .filter(|position| !position.is_empty())
+                .map(|position| {
+                    position
+                        .split_once('-')
+                        .and_then(|(start, end)| {
+                            Some(EmotePosition {
+                                start_position: start.parse().ok()?,
+                                end_position: end.parse().ok()?,
+                            })
+                        })
+                        .ok_or_else(|| format!("Invalid emote position {:?}", position))
+                })
+                .collect::<Result<_, _>>()?;
+            Ok(Emote { id: id.to_string(), positions })
+        })
+        .collect()
+}
+
+/// The message a reply was sent to, from the `reply-parent-*` tags.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ReplyParent {
+    pub msg_id: String,
+    pub user_id: Option<String>,
+    pub user_login: Option<String>,
+    pub display_name: Option<String>,
+    pub msg_body: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct PrivmsgTags {
+    pub id: Option<String>,
+    pub user_id: Option<String>,
+    pub room_id: Option<String>,
+    pub display_name: Option<String>,
+    pub color: Option<String>,
+    pub badges: Badges,
+    pub badge_info: Badges,
+    pub emotes: Vec<Emote>,
+    pub moderator: bool,
+    pub subscriber: bool,
+    pub turbo: bool,
+    pub vip: bool,
+    pub first_msg: bool,
+    pub returning_chatter: bool,
+    pub bits: Option<u64>,
+    pub tmi_sent_ts: Option<u64>,
+    pub reply_parent: Option<ReplyParent>,
+    /// Tags without a typed field.
+    pub other: HashMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct UserStateTags {
+    pub id: Option<String>,
+    pub display_name: Option<String>,
+    pub color: Option<String>,
+    pub badges: Badges,
+    pub badge_info: Badges,
+    pub emote_sets: Vec<String>,
+    pub moderator: bool,
+    pub subscriber: bool,
+    pub turbo: bool,
+    pub user_type: Option<String>,
+    /// Tags without a typed field.
+    pub other: HashMap<String, String>,
+}
+
+/// Channel settings, only changed settings are present on updates.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct RoomStateTags {
+    pub room_id: Option<String>,
+    pub emote_only: Option<bool>,
+    /// Minutes a user must follow before chatting, `-1` when disabled.
+    pub followers_only: Option<i64>,
+    pub r9k: Option<bool>,
+    /// Seconds between messages.
+    pub slow: Option<u64>,
+    pub subs_only: Option<bool>,
+    /// Tags without a typed field.
+    pub other: HashMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct UserNoticeTags {
+    pub id: Option<String>,
+    pub msg_id: Option<String>,
+    pub user_id: Option<String>,
+    pub room_id: Option<String>,
+    pub login: Option<String>,
+    pub display_name: Option<String>,
+    pub color: Option<String>,
+    pub badges: Badges,
+    pub badge_info: Badges,
+    pub emotes: Vec<Emote>,
+    pub moderator: bool,
+    pub subscriber: bool,
+    pub turbo: bool,
+    pub system_msg: Option<String>,
+    pub tmi_sent_ts: Option<u64>,
+    /// `msg-param-*` tags without the prefix, their meaning depends on `msg_id`.
+    pub msg_params: HashMap<String, String>,
+    /// Tags without a typed field.
+    pub other: HashMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClearChatTags {
+    pub room_id: Option<String>,
+    pub target_user_id: Option<String>,
+    /// Timeout length in seconds, absent for permanent bans and full clears.
+    pub ban_duration: Option<u64>,
+    pub tmi_sent_ts: Option<u64>,
+    /// Tags without a typed field.
+    pub other: HashMap<String, String>,
+}
+
+/// IRCv3 tags of a message, typed according to the command they came with.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum Tags {
+    Privmsg(PrivmsgTags),
+    UserState(UserStateTags),
+    RoomState(RoomStateTags),
+    UserNotice(UserNoticeTags),
+    ClearChat(ClearChatTags),
+    /// Tags of any other command.
+    Other(HashMap<String, String>),
+}
+
+impl Tags {
+    /// Types the raw `key=value` pairs for the given command. Values that
+    /// cannot be typed are kept in `other`.
+    pub fn new(command: &str, raw_tags: HashMap<String, String>) -> Self {
+        let mut tags = TagMap(raw_tags);
+        match command {
+            "PRIVMSG" =>
+                Self::Privmsg(PrivmsgTags {
+                    id: tags.string("id"),
+                    user_id: tags.string("user-id"),
+                    room_id: tags.string("room-id"),
+                    display_name: tags.string("display-name"),
+                    color: tags.string("color"),
+                    badges: tags.badges("badges"),
+                    badge_info: tags.badges("badge-info"),
+                    emotes: tags.emotes("emotes"),
+                    moderator: tags.flag("mod"),
+                    subscriber: tags.flag("subscriber"),
+                    turbo: tags.flag("turbo"),
+                    vip: tags.flag("vip"),
+                    first_msg: tags.flag("first-msg"),
+                    returning_chatter: tags.flag("returning-chatter"),
+                    bits: tags.number("bits"),
+                    tmi_sent_ts: tags.number("tmi-sent-ts"),
+                    reply_parent: tags.reply_parent(),
+                    other: tags.0,
+                }),
+            "USERSTATE" | "GLOBALUSERSTATE" =>
+                Self::UserState(UserStateTags {
+                    id: tags.string("id"),
+                    display_name: tags.string("display-name"),
+                    color: tags.string("color"),
+                    badges: tags.badges("badges"),
+                    badge_info: tags.badges("badge-info"),
+                    emote_sets: tags
+                        .string("emote-sets")
+                        .map(|sets| sets.split(',').map(String::from).collect())
+                        .unwrap_or_default(),
+                    moderator: tags.flag("mod"),
+                    subscriber: tags.flag("subscriber"),
+                    turbo: tags.flag("turbo"),
+                    user_type: tags.string("user-type"),
+                    other: tags.0,
+                }),
+            "ROOMSTATE" =>
+                Self::RoomState(RoomStateTags {
+                    room_id: tags.string("room-id"),
+                    emote_only: tags.optional_flag("emote-only"),
+                    followers_only: tags.number("followers-only"),
+                    r9k: tags.optional_flag("r9k"),
+                    slow: tags.number("slow"),
+                    subs_only: tags.optional_flag("subs-only"),
+                    other: tags.0,
+                }),
+            "USERNOTICE" =>
+                Self::UserNotice(UserNoticeTags {
+                    id: tags.string("id"),
+                    msg_id: tags.string("msg-id"),
+                    user_id: tags.string("user-id"),
+                    room_id: tags.string("room-id"),
+                    login: tags.string("login"),
+                    display_name: tags.string("display-name"),
+                    color: tags.string("color"),
+                    badges: tags.badges("badges"),
+                    badge_info: tags.badges("badge-info"),
+                    emotes: tags.emotes("emotes"),
+                    moderator: tags.flag("mod"),
+                    subscriber: tags.flag("subscriber"),
+                    turbo: tags.flag("turbo"),
+                    system_msg: tags.string("system-msg"),
+                    tmi_sent_ts: tags.number("tmi-sent-ts"),
+                    msg_params: tags.prefixed("msg-param-"),
+                    other: tags.0,
+                }),
+            "CLEARCHAT" =>
+                Self::ClearChat(ClearChatTags {
+                    room_id: tags.string("room-id"),
+                    target_user_id: tags.string("target-user-id"),
+                    ban_duration: tags.number("ban-duration"),
+                    tmi_sent_ts: tags.number("tmi-sent-ts"),
+                    other: tags.0,
+                }),
+            _ => Self::Other(tags.0),
+        }
+    }
+
+    pub fn display_name(&self) -> Option<&str> {
+        match self {
+            Self::Privmsg(tags) => tags.display_name.as_deref(),
+            Self::UserState(tags) => tags.display_name.as_deref(),
+            Self::UserNotice(tags) => tags.display_name.as_deref(),
+            _ => None,
+        }
+    }
+
+    pub fn badges(&self) -> Option<&Badges> {
+        match self {
+            Self::Privmsg(tags) => Some(&tags.badges),
+            Self::UserState(tags) => Some(&tags.badges),
+            Self::UserNotice(tags) => Some(&tags.badges),
+            _ => None,
+        }
+    }
+}
+
+/// Raw tags that are moved into typed fields one by one, leaving the unknown ones.
+struct TagMap(HashMap<String, String>);
+
+impl TagMap {
+    /// Takes a tag, treating an empty value like a missing one.
+    fn string(&mut self, key: &str) -> Option<String> {
+        self.0.remove(key).filter(|value| !value.is_empty())
+    }
+
+    fn number<T: FromStr>(&mut self, key: &str) -> Option<T> {
+        self.string(key).and_then(|value| value.parse().ok())
+    }
+
+    fn optional_flag(&mut self, key: &str) -> Option<bool> {
+        self.string(key).map(|value| value != "0")
+    }
+
+    fn flag(&mut self, key: &str) -> bool {
+        self.optional_flag(key).unwrap_or(false)
+    }
+
+    fn badges(&mut self, key: &str) -> Badges {
+        self.string(key)
+            .map(|value| Badges::parse(&value))
+            .unwrap_or_default()
+    }
+
+    /// Takes the emotes, leaving malformed ones in the map so the rest of
+    /// the message is still usable.
+    fn emotes(&mut self, key: &str) -> Vec<Emote> {
+        let Some(value) = self.string(key) else {
+            return Vec::new();
+        };
+        parse_emotes(&value).unwrap_or_else(|_| {
+            self.0.insert(key.to_string(), value);
+            Vec::new()
+        })
+    }
+
+    fn prefixed(&mut self, prefix: &str) -> HashMap<String, String> {
+        let keys: Vec<String> = self.0
+            .keys()
+            .filter(|key| key.starts_with(prefix))
+            .cloned()
+            .collect();
+        keys.into_iter()
+            .filter_map(|key| {
+                let value = self.0.remove(&key)?;
+                Some((key[prefix.len()..].to_string(), value))
+            })
+            .collect()
+    }
+
+    fn reply_parent(&mut self) -> Option<ReplyParent> {
+        let msg_id = self.string("reply-parent-msg-id");
+        let user_id = self.string("reply-parent-user-id");
+        let user_login = self.string("reply-parent-user-login");
+        let display_name = self.string("reply-parent-display-name");
+        let msg_body = self.string("reply-parent-msg-body");
+        Some(ReplyParent {
+            msg_id: msg_id?,
+            user_id,
+            user_login,
+            display_name,
+            msg_body,
+        })
+    }
+}
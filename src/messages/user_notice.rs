@@ -0,0 +1,233 @@
+use std::{ collections::HashMap, fmt::Display };
+
+use regex::{ Captures, Regex };
+use serde::{ Deserialize, Serialize };
+
+use crate::messages::tags::UserNoticeTags;
+
+/// Placeholders every event template may use.
+pub const COMMON_PLACEHOLDERS: [&str; 2] = ["sender", "message"];
+
+/// Event specific placeholders by `msg-id`.
+const EVENT_PLACEHOLDERS: [(&str, &[&str]); 7] = [
+    ("sub", &["plan", "months"]),
+    ("resub", &["plan", "months", "streak"]),
+    ("subgift", &["plan", "recipient", "gift_months"]),
+    ("submysterygift", &["plan", "count", "total"]),
+    ("raid", &["viewers"]),
+    ("announcement", &["color"]),
+    ("bitsbadgetier", &["threshold"]),
+];
+
+/// Subscription tier from `msg-param-sub-plan`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum SubPlan {
+    Prime,
+    Tier1,
+    Tier2,
+    Tier3,
+    Other(String),
+}
+
+impl SubPlan {
+    fn from_param(plan: Option<&String>) -> Self {
+        match plan.map(String::as_str) {
+            Some("Prime") => Self::Prime,
+            Some("1000") | None => Self::Tier1,
+            Some("2000") => Self::Tier2,
+            Some("3000") => Self::Tier3,
+            Some(other) => Self::Other(other.to_string()),
+        }
+    }
+}
+
+impl Display for SubPlan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Prime => write!(f, "Prime"),
+            Self::Tier1 => write!(f, "Tier 1"),
+            Self::Tier2 => write!(f, "Tier 2"),
+            Self::Tier3 => write!(f, "Tier 3"),
+            Self::Other(plan) => write!(f, "{}", plan),
+        }
+    }
+}
+
+/// A USERNOTICE typed by its `msg-id` tag.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum UserNoticeEvent {
+    Sub {
+        plan: SubPlan,
+        months: u64,
+    },
+    Resub {
+        plan: SubPlan,
+        months: u64,
+        /// Zero if the user did not share their streak.
+        streak_months: u64,
+    },
+    SubGift {
+        plan: SubPlan,
+        recipient: String,
+        gift_months: u64,
+        /// Set when the gift is one of the subs of a `submysterygift`.
+        community_gift_id: Option<String>,
+    },
+    SubMysteryGift {
+        plan: SubPlan,
+        count: u64,
+        /// Subs the sender gifted in the channel so far, zero if not shared.
+        sender_total: u64,
+    },
+    Raid {
+        viewer_count: u64,
+    },
+    Announcement {
+        color: String,
+    },
+    BitsBadgeTier {
+        threshold: u64,
+    },
+    /// Any other `msg-id`, only the common placeholders are available.
+    Other {
+        msg_id: String,
+    },
+}
+
+impl UserNoticeEvent {
+    /// Types the event, `None` if the notice has no `msg-id`.
+    pub fn from_tags(tags: &UserNoticeTags) -> Option<Self> {
+        let params = &tags.msg_params;
+        let plan = || SubPlan::from_param(params.get("sub-plan"));
+
+        let event = match tags.msg_id.as_deref()? {
+            "sub" =>
+                Self::Sub {
+                    plan: plan(),
+                    months: number(params, "cumulative-months").max(1),
+                },
+            "resub" =>
+                Self::Resub {
+                    plan: plan(),
+                    months: number(params, "cumulative-months"),
+                    streak_months: number(params, "streak-months"),
+                },
+            "subgift" =>
+                Self::SubGift {
+                    plan: plan(),
+                    recipient: params
+                        .get("recipient-display-name")
+                        .or_else(|| params.get("recipient-user-name"))
+                        .cloned()
+                        .unwrap_or_default(),
+                    gift_months: number(params, "gift-months").max(1),
+                    community_gift_id: params.get("community-gift-id").cloned(),
+                },
+            "submysterygift" =>
+                Self::SubMysteryGift {
+                    plan: plan(),
+                    count: number(params, "mass-gift-count"),
+                    sender_total: number(params, "sender-count"),
+                },
+            "raid" =>
+                Self::Raid {
+                    viewer_count: number(params, "viewerCount"),
+                },
+            "announcement" =>
+                Self::Announcement {
+                    color: params
+                        .get("color")
+                        .cloned()
+                        .unwrap_or_else(|| "PRIMARY".to_string()),
+                },
+            "bitsbadgetier" =>
+                Self::BitsBadgeTier {
+                    threshold: number(params, "threshold"),
+                },
+            msg_id => Self::Other { msg_id: msg_id.to_string() },
+        };
+        Some(event)
+    }
+
+    /// The `msg-id` the event was built from, used to look up its response.
+    pub fn kind(&self) -> &str {
+        match self {
+            Self::Sub { .. } => "sub",
+            Self::Resub { .. } => "resub",
+            Self::SubGift { .. } => "subgift",
+            Self::SubMysteryGift { .. } => "submysterygift",
+            Self::Raid { .. } => "raid",
+            Self::Announcement { .. } => "announcement",
+            Self::BitsBadgeTier { .. } => "bitsbadgetier",
+            Self::Other { msg_id } => msg_id,
+        }
+    }
+
+    /// Whether the event is a single gift of a community gift, which Twitch
+    /// sends once per recipient after the `submysterygift` announcing them.
+    pub fn is_part_of_community_gift(&self) -> bool {
+        matches!(self, Self::SubGift { community_gift_id: Some(_), .. })
+    }
+
+    /// Event specific placeholder values.
+    pub fn placeholders(&self) -> Vec<(&'static str, String)> {
+        match self {
+            Self::Sub { plan, months } =>
+                vec![("plan", plan.to_string()), ("months", months.to_string())],
+            Self::Resub { plan, months, streak_months } =>
+                vec![
+                    ("plan", plan.to_string()),
+                    ("months", months.to_string()),
+                    ("streak", streak_months.to_string())
+                ],
+            Self::SubGift { plan, recipient, gift_months, .. } =>
+                vec![
+                    ("plan", plan.to_string()),
+                    ("recipient", recipient.clone()),
+                    ("gift_months", gift_months.to_string())
+                ],
+            Self::SubMysteryGift { plan, count, sender_total } =>
+                vec![
+                    ("plan", plan.to_string()),
+                    ("count", count.to_string()),
+                    ("total", sender_total.to_string())
+                ],
+            Self::Raid { viewer_count } => vec![("viewers", viewer_count.to_string())],
+            Self::Announcement { color } => vec![("color", color.clone())],
+            Self::BitsBadgeTier { threshold } => vec![("threshold", threshold.to_string())],
+            Self::Other { .. } => Vec::new(),
+        }
+    }
+
+    /// Fills in a response template for this event in a single pass, so
+    /// placeholders in the viewer's message are inserted verbatim.
+    pub fn render(&self, template: &str, sender: &str, message: &str) -> String {
+        let placeholders = self.placeholders();
+        let re = Regex::new(r"\{(\w+)\}").unwrap();
+        re.replace_all(template, |captures: &Captures| {
+            match &captures[1] {
+                "sender" => sender.to_string(),
+                "message" => message.to_string(),
+                name =>
+                    placeholders
+                        .iter()
+                        .find(|(placeholder, _)| *placeholder == name)
+                        .map_or_else(|| captures[0].to_string(), |(_, value)| value.clone()),
+            }
+        }).into_owned()
+    }
+}
+
+/// Placeholders a template for the given `msg-id` may use besides the common ones.
+pub fn event_placeholders(kind: &str) -> &'static [&'static str] {
+    EVENT_PLACEHOLDERS.iter()
+        .find(|(event, _)| *event == kind)
+        .map_or(&[], |(_, placeholders)| placeholders)
+}
+
+fn number(params: &HashMap<String, String>, key: &str) -> u64 {
+    params
+        .get(key)
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(0)
+}
@@ -0,0 +1,77 @@
+use std::{ collections::{ HashMap, HashSet }, sync::Arc };
+
+use tokio::time::Instant;
+use tracing::info;
+
+use crate::{
+    config::{ channel_name, command_parser::Welcome },
+    messages::{ cooldown::{ Clock, TokioClock }, private_message::PrivateMessageResponse, tags::PrivmsgTags },
+};
+
+/// Most chatters remembered per channel for `first_in_stream`, the set starts
+/// over once it is full.
+const MAX_SEEN_PER_CHANNEL: usize = 10_000;
+
+/// Tracks who was already seen and when each channel was last greeted.
+pub struct Welcomer {
+    seen: HashMap<String, HashSet<String>>,
+    last_welcomes: HashMap<String, Instant>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Default for Welcomer {
+    fn default() -> Self {
+        Self::with_clock(Arc::new(TokioClock))
+    }
+}
+
+impl Welcomer {
+    pub fn with_clock(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            seen: HashMap::new(),
+            last_welcomes: HashMap::new(),
+            clock,
+        }
+    }
+
+    /// Greets the sender if this is their first message, unless the channel
+    /// was greeted within the welcome's cooldown.
+    pub fn welcome(
+        &mut self,
+        welcome: &Welcome,
+        tags: &PrivmsgTags,
+        channel: &str
+    ) -> Option<PrivateMessageResponse> {
+        let channel_key = channel_name(channel);
+        let is_new_in_stream = welcome.first_in_stream &&
+            tags.user_id.as_ref().is_some_and(|user_id| {
+                let seen = self.seen.entry(channel_key.clone()).or_default();
+                if seen.len() >= MAX_SEEN_PER_CHANNEL {
+                    seen.clear();
+                }
+                seen.insert(user_id.clone())
+            });
+        if !tags.first_msg && !is_new_in_stream {
+            return None;
+        }
+
+        let display_name = tags.display_name.as_deref()?;
+        let now = self.clock.now();
+        if let Some(last_welcome) = self.last_welcomes.get(&channel_key) {
+            if now.duration_since(*last_welcome) < welcome.cooldown.0 {
+                info!("Welcome in {} is still under cooldown, skipping {}", channel, display_name);
+                return None;
+            }
+        }
+        self.last_welcomes.insert(channel_key, now);
+
+        info!("Welcomed {} in {}", display_name, channel);
+        Some(PrivateMessageResponse::from(channel, &welcome.response.replace("{sender}", display_name)))
+    }
+
+    /// Forgets who was seen in `channel`, called whenever the bot joins or
+    /// leaves it.
+    pub fn forget_channel(&mut self, channel: &str) {
+        self.seen.remove(&channel_name(channel));
+    }
+}
@@ -0,0 +1,185 @@
+use std::{ collections::{ HashSet, VecDeque }, str::FromStr, time::Duration };
+
+use tokio::time::Instant;
+use tracing::warn;
+
+use crate::{ config::channel_name, messages::private_message::PrivateMessageResponse };
+
+/// Twitch allows 20 messages per 30 seconds for regular users.
+const NORMAL_LIMIT: usize = 20;
+/// Moderators, VIPs and the broadcaster may send 100 messages per 30 seconds.
+const PRIVILEGED_LIMIT: usize = 100;
+const LIMIT_WINDOW: Duration = Duration::from_secs(30);
+const DEFAULT_QUEUE_SIZE: usize = 100;
+
+/// What happens to a message that exceeds the rate limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Hold up to the given number of messages and send them once budget is available.
+    Queue(usize),
+    /// Discard the message.
+    Drop,
+}
+
+impl Default for OverflowPolicy {
+    fn default() -> Self {
+        Self::Queue(DEFAULT_QUEUE_SIZE)
+    }
+}
+
+impl FromStr for OverflowPolicy {
+    type Err = String;
+
+    /// Parses `drop`, `queue` or `queue:<size>`.
+    fn from_str(policy: &str) -> Result<Self, Self::Err> {
+        let normalized = policy.trim().to_lowercase();
+        match normalized.as_str() {
+            "drop" => Ok(Self::Drop),
+            "queue" => Ok(Self::default()),
+            _ =>
+                match normalized.strip_prefix("queue:") {
+                    Some(size) =>
+                        size
+                            .parse()
+                            .map(Self::Queue)
+                            .map_err(|_| format!("Invalid queue size in rate limit policy: {}", size)),
+                    None => Err(format!("Invalid rate limit policy: {}", policy)),
+                }
+        }
+    }
+}
+
+/// Times of the messages sent within the last window.
+struct SendLog {
+    limit: usize,
+    window: Duration,
+    sent: VecDeque<Instant>,
+}
+
+impl SendLog {
+    fn new(limit: usize, window: Duration) -> Self {
+        Self {
+            limit,
+            window,
+            sent: VecDeque::with_capacity(limit),
+        }
+    }
+
+    fn prune(&mut self, now: Instant) {
+        while self.sent.front().is_some_and(|sent| now.saturating_duration_since(*sent) >= self.window) {
+            self.sent.pop_front();
+        }
+    }
+
+    fn has_budget(&self) -> bool {
+        self.sent.len() < self.limit
+    }
+
+    fn record(&mut self, now: Instant) {
+        self.sent.push_back(now);
+    }
+
+    /// Time until the oldest send leaves the window, zero if there is budget left.
+    fn time_until_budget(&self, now: Instant) -> Duration {
+        match self.sent.front() {
+            Some(oldest) if !self.has_budget() => (*oldest + self.window).saturating_duration_since(now),
+            _ => Duration::ZERO,
+        }
+    }
+}
+
+/// Sliding window scheduler for outgoing chat messages, never sending more
+/// than the limit within any 30 second window.
+///
+/// Every message counts against the privileged budget, messages to channels
+/// where the bot has no moderator/VIP badge also count against the normal one.
+pub struct RateLimiter {
+    normal: SendLog,
+    privileged: SendLog,
+    privileged_channels: HashSet<String>,
+    policy: OverflowPolicy,
+    queue: VecDeque<PrivateMessageResponse>,
+}
+
+impl RateLimiter {
+    pub fn new(policy: OverflowPolicy) -> Self {
+        Self {
+            normal: SendLog::new(NORMAL_LIMIT, LIMIT_WINDOW),
+            privileged: SendLog::new(PRIVILEGED_LIMIT, LIMIT_WINDOW),
+            privileged_channels: HashSet::new(),
+            policy,
+            queue: VecDeque::new(),
+        }
+    }
+
+    /// Records whether the bot is moderator, VIP or broadcaster in `channel`.
+    pub fn set_privileged(&mut self, channel: &str, privileged: bool) {
+        let channel = channel_name(channel);
+        if privileged {
+            self.privileged_channels.insert(channel);
+        } else {
+            self.privileged_channels.remove(&channel);
+        }
+    }
+
+    /// Adds a message to the back of the queue, applying the overflow policy.
+    pub fn submit(&mut self, message: PrivateMessageResponse) {
+        let is_privileged = self.is_privileged(&message);
+        if self.queue.is_empty() && self.can_send(is_privileged, Instant::now()) {
+            self.queue.push_back(message);
+            return;
+        }
+
+        match self.policy {
+            OverflowPolicy::Queue(size) if self.queue.len() < size => self.queue.push_back(message),
+            _ => warn!("Rate limit exceeded, dropping message: {}", message),
+        }
+    }
+
+    /// Puts a message that could not be written back at the front of the queue.
+    pub fn requeue(&mut self, message: PrivateMessageResponse) {
+        self.queue.push_front(message);
+    }
+
+    /// Takes the next message if there is budget left to send it.
+    pub fn pop_ready(&mut self) -> Option<PrivateMessageResponse> {
+        let now = Instant::now();
+        let is_privileged = self.is_privileged(self.queue.front()?);
+        if !self.can_send(is_privileged, now) {
+            return None;
+        }
+
+        self.privileged.record(now);
+        if !is_privileged {
+            self.normal.record(now);
+        }
+        self.queue.pop_front()
+    }
+
+    /// Time until the first queued message can be sent, `None` if the queue is empty.
+    pub fn next_ready_in(&mut self) -> Option<Duration> {
+        let now = Instant::now();
+        let is_privileged = self.is_privileged(self.queue.front()?);
+
+        self.privileged.prune(now);
+        let mut wait = self.privileged.time_until_budget(now);
+        if !is_privileged {
+            self.normal.prune(now);
+            wait = wait.max(self.normal.time_until_budget(now));
+        }
+        Some(wait)
+    }
+
+    fn is_privileged(&self, message: &PrivateMessageResponse) -> bool {
+        self.privileged_channels.contains(&channel_name(message.channel()))
+    }
+
+    fn can_send(&mut self, is_privileged: bool, now: Instant) -> bool {
+        self.privileged.prune(now);
+        if is_privileged {
+            return self.privileged.has_budget();
+        }
+        self.normal.prune(now);
+        self.privileged.has_budget() && self.normal.has_budget()
+    }
+}
@@ -1,91 +1,426 @@
-use tokio::{ io::{ split, AsyncBufReadExt, AsyncWriteExt, BufReader }, net::TcpStream, sync::mpsc };
+use std::{ future::Future, pin::Pin, sync::Mutex, time::Duration };
+
+use rand::Rng;
+use tokio::{
+    io::{
+        split,
+        ErrorKind,
+        AsyncBufReadExt,
+        AsyncRead,
+        AsyncWrite,
+        AsyncWriteExt,
+        BufReader,
+        Lines,
+        ReadHalf,
+        WriteHalf,
+    },
+    net::TcpStream,
+    sync::mpsc,
+    time::Instant,
+};
 use tokio_native_tls::native_tls::TlsConnector;
 use tokio_native_tls::TlsConnector as TokioTlsConnector;
-use tracing::{ info, error };
+use tracing::{ info, error, warn };
+
+use crate::{
+    config::{ channel_name, connection::ConnectionConfig },
+    error::ConnectError,
+    messages::{ irc_message::IrcMessage, private_message::PrivateMessageResponse },
+    rate_limiter::{ OverflowPolicy, RateLimiter },
+};
+
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// How long a session has to stay up before the backoff starts over.
+const STABLE_SESSION: Duration = Duration::from_secs(60);
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// NOTICE texts Twitch sends when it rejects the login.
+const AUTHENTICATION_FAILURES: [&str; 2] = [
+    "Login authentication failed",
+    "Improperly formatted auth",
+];
+
+/// Plain TCP or TLS connection to the IRC server.
+trait AsyncStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> AsyncStream for T {}
+
+type IrcStream = Box<dyn AsyncStream>;
+type IrcReader = Lines<BufReader<ReadHalf<IrcStream>>>;
+type IrcWriter = WriteHalf<IrcStream>;
+type PendingSession<'a> = Pin<Box<dyn Future<Output = Result<(IrcReader, IrcWriter), ConnectError>> + Send + 'a>>;
+
+/// Messages sent from the chat bot to the tcp_handler.
+#[derive(Debug)]
+pub enum BotMessage {
+    /// A raw IRC line, written to the socket as is.
+    Raw(String),
+    /// A chat message, subject to the outgoing rate limit.
+    Privmsg(PrivateMessageResponse),
+    /// The bot's own badges in a channel, taken from USERSTATE.
+    UserState {
+        channel: String,
+        privileged: bool,
+    },
+    /// Twitch asked us to move to a fresh connection.
+    Reconnect,
+    /// Join a channel and keep it joined across reconnects.
+    Join(String),
+    /// Leave a channel.
+    Part(String),
+}
 
 pub struct TcpHandler {
+    connection: ConnectionConfig,
     nickname: String,
     oauth_token: String,
-    channel: String,
+    channels: Mutex<Vec<String>>,
+    overflow_policy: OverflowPolicy,
     from_tcp_sender: mpsc::UnboundedSender<String>,
 }
 
+/// Why a single connection to Twitch came to an end.
+enum SessionEnd {
+    /// The socket was closed or errored, a new connection should be opened.
+    Disconnected,
+    /// The chat bot dropped its side of the channels, nothing left to do.
+    BotClosed,
+}
+
 impl TcpHandler {
     pub fn new(
+        connection: ConnectionConfig,
         nickname: &str,
         oauth_token: &str,
-        channel: &str,
+        channels: &[String],
+        overflow_policy: OverflowPolicy,
         from_tcp_sender: mpsc::UnboundedSender<String>
     ) -> Self {
         Self {
+            connection,
             nickname: nickname.to_string(),
             oauth_token: oauth_token.to_string(),
-            channel: channel.to_string(),
+            channels: Mutex::new(channels.iter().map(|channel| channel_name(channel)).collect()),
+            overflow_policy,
             from_tcp_sender,
         }
     }
 
-    pub async fn run(&mut self, mut from_bot_receiver: mpsc::UnboundedReceiver<String>) {
-        let server_address = "irc.chat.twitch.tv";
-        let server_port = 6697;
+    /// Keeps a connection to Twitch alive until the chat bot goes away.
+    ///
+    /// Whenever the connection drops a new one is opened after a jittered
+    /// exponential backoff. Messages queued in `from_bot_receiver` while
+    /// disconnected are kept and written once the new session is authenticated.
+    ///
+    /// Returns an error only if the server rejects the login.
+    pub async fn run(
+        &mut self,
+        mut from_bot_receiver: mpsc::UnboundedReceiver<BotMessage>
+    ) -> Result<(), ConnectError> {
+        let mut backoff = Backoff::new();
+        // Message that was taken from the queue but could not be written
+        let mut pending: Option<String> = None;
+        // Chat messages waiting for rate limit budget, kept across reconnects
+        let mut rate_limiter = RateLimiter::new(self.overflow_policy);
+
+        loop {
+            match self.open_session().await {
+                Ok(session) => {
+                    info!("Connected to IRC server {}:{}", self.connection.host, self.connection.port);
+                    let connected_at = Instant::now();
+
+                    let session_end = self.serve(
+                        session,
+                        &mut from_bot_receiver,
+                        &mut pending,
+                        &mut rate_limiter
+                    ).await;
+                    // A server that accepts the login and drops us right away
+                    // must not be reconnected to at the initial delay forever
+                    if connected_at.elapsed() >= STABLE_SESSION {
+                        backoff.reset();
+                    }
+                    match session_end {
+                        SessionEnd::Disconnected => warn!("Disconnected from Twitch IRC server"),
+                        SessionEnd::BotClosed => {
+                            info!("Chat bot closed, stopping tcp_handler");
+                            return Ok(());
+                        }
+                    }
+                }
+                Err(ConnectError::AuthenticationFailed(notice)) => {
+                    error!("Twitch rejected the login: {}", notice);
+                    return Err(ConnectError::AuthenticationFailed(notice));
+                }
+                Err(ConnectError::BotClosed) => {
+                    info!("Chat bot closed during login, stopping tcp_handler");
+                    return Ok(());
+                }
+                Err(error) => error!("Connecting to Twitch failed: {}", error),
+            }
+
+            let delay = backoff.next_delay();
+            info!("Reconnecting in {:.1}s", delay.as_secs_f64());
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    async fn connect(&self) -> std::io::Result<IrcStream> {
+        let ConnectionConfig { host, port, tls } = &self.connection;
 
         // Connect to the server over TCP
-        let tcp_stream = TcpStream::connect((server_address, server_port)).await.expect(
-            "Connecting to Twitch failed"
-        );
+        let tcp_stream = TcpStream::connect((host.as_str(), *port)).await?;
+        if !tls {
+            return Ok(Box::new(tcp_stream));
+        }
 
         // Set up TLS
-        let native_tls_connector = TlsConnector::new().expect("Creating TlsConnector failed");
+        let native_tls_connector = TlsConnector::new().map_err(std::io::Error::other)?;
         let tls_connector = TokioTlsConnector::from(native_tls_connector);
         let tls_stream = tls_connector
-            .connect(server_address, tcp_stream).await
-            .expect("TLS connection failed");
+            .connect(host, tcp_stream).await
+            .map_err(std::io::Error::other)?;
+        Ok(Box::new(tls_stream))
+    }
+
+    /// Opens a new connection and logs in, waiting for the server to accept it.
+    async fn open_session(&self) -> Result<(IrcReader, IrcWriter), ConnectError> {
+        let (read_half, mut write_half) = split(self.connect().await?);
+        let mut lines = BufReader::new(read_half).lines();
+        self.authenticate(&mut write_half).await?;
 
-        let (read_half, mut write_half) = split(tls_stream);
+        tokio::time
+            ::timeout(HANDSHAKE_TIMEOUT, self.await_welcome(&mut lines)).await
+            .map_err(|_| std::io::Error::new(ErrorKind::TimedOut, "No reply to login"))??;
 
-        info!("Connected to Twitch IRC server");
+        Ok((lines, write_half))
+    }
 
-        // Clone the sender to be able to move it into a thread
-        let from_tcp_sender_into = self.from_tcp_sender.clone();
+    /// Reads until the `001` welcome or a NOTICE rejecting the login.
+    async fn await_welcome(&self, lines: &mut IrcReader) -> Result<(), ConnectError> {
+        while let Some(raw_message) = lines.next_line().await? {
+            if let Ok(message) = IrcMessage::parse(&raw_message) {
+                let text = message.params.last().map(String::as_str).unwrap_or_default();
+                match message.command.as_str() {
+                    "001" => {
+                        info!("Logged in as {}", self.nickname);
+                        return Ok(());
+                    }
+                    "NOTICE" if AUTHENTICATION_FAILURES.iter().any(|failure| text.contains(failure)) => {
+                        return Err(ConnectError::AuthenticationFailed(text.to_string()));
+                    }
+                    _ => {}
+                }
+            }
 
-        // Task to read from TCP and send to internal channel
-        tokio::spawn(async move {
-            let mut reader = BufReader::new(read_half);
-            let mut buffer = String::new();
-            info!("Reading from TCP started");
+            if self.from_tcp_sender.send(raw_message).is_err() {
+                return Err(ConnectError::BotClosed);
+            }
+        }
 
-            while reader.read_line(&mut buffer).await.is_ok() {
-                let raw_message = buffer.trim_end().to_string();
+        Err(std::io::Error::new(ErrorKind::UnexpectedEof, "Connection closed during login").into())
+    }
 
-                buffer.clear();
+    async fn serve(
+        &self,
+        session: (IrcReader, IrcWriter),
+        from_bot_receiver: &mut mpsc::UnboundedReceiver<BotMessage>,
+        pending: &mut Option<String>,
+        rate_limiter: &mut RateLimiter
+    ) -> SessionEnd {
+        let (mut lines, mut write_half) = session;
+        // New connection being opened after a RECONNECT, the old one keeps
+        // serving until it is ready
+        let mut migration: Option<PendingSession<'_>> = None;
+        // JOIN/PART lines written while migrating, the new connection may have
+        // logged in with the old channel list
+        let mut replay: Vec<String> = Vec::new();
 
-                if let Err(error) = from_tcp_sender_into.send(raw_message) {
-                    error!("Sending message to chat_bot in tcp_handler failed: {}", error);
-                    break;
-                }
+        if let Some(message) = pending.take() {
+            if let Err(error) = write_line(&mut write_half, &message).await {
+                error!("Writing to TCP failed: {}", error);
+                *pending = Some(message);
+                return SessionEnd::Disconnected;
             }
-        });
-
-        // Clone token, nickname and channel to be able to move them into a thread
-        let oauth_token_into = self.oauth_token.clone();
-        let nickname_into = self.nickname.clone();
-        let channel_into = self.channel.clone();
-
-        // Task to read from internal channel and write back to TCP
-        tokio::spawn(async move {
-            info!("Writing to TCP started");
-            write_half
-                .write_all(format!("PASS {}\r\n", oauth_token_into).as_bytes()).await
-                .unwrap();
-            // Authenticate
-            write_half.write_all(format!("NICK {}\r\n", nickname_into).as_bytes()).await.unwrap();
-            write_half.write_all(format!("JOIN #{}\r\n", channel_into).as_bytes()).await.unwrap();
-            write_half.write_all("CAP REQ :twitch.tv/tags\r\n".as_bytes()).await.unwrap();
-            write_half.write_all("CAP REQ :twitch.tv/tags\r\n".as_bytes()).await.unwrap();
-
-            while let Some(message) = from_bot_receiver.recv().await {
-                write_half.write_all(format!("{}\r\n", message).as_bytes()).await.unwrap();
+        }
+
+        if let Err(error) = flush(&mut write_half, rate_limiter).await {
+            error!("Writing to TCP failed: {}", error);
+            return SessionEnd::Disconnected;
+        }
+
+        loop {
+            let next_ready_in = rate_limiter.next_ready_in();
+
+            tokio::select! {
+                line = lines.next_line() => match line {
+                    Ok(Some(raw_message)) => {
+                        if let Err(error) = self.from_tcp_sender.send(raw_message) {
+                            error!("Sending message to chat_bot in tcp_handler failed: {}", error);
+                            return SessionEnd::BotClosed;
+                        }
+                    }
+                    Ok(None) => {
+                        warn!("Twitch closed the connection");
+                        return SessionEnd::Disconnected;
+                    }
+                    Err(error) => {
+                        error!("Reading from TCP failed: {}", error);
+                        return SessionEnd::Disconnected;
+                    }
+                },
+                message = from_bot_receiver.recv() => match message {
+                    Some(BotMessage::Raw(message)) => {
+                        if let Err(error) = write_line(&mut write_half, &message).await {
+                            error!("Writing to TCP failed: {}", error);
+                            *pending = Some(message);
+                            return SessionEnd::Disconnected;
+                        }
+                    }
+                    Some(BotMessage::Privmsg(message)) => {
+                        rate_limiter.submit(message);
+                        if let Err(error) = flush(&mut write_half, rate_limiter).await {
+                            error!("Writing to TCP failed: {}", error);
+                            return SessionEnd::Disconnected;
+                        }
+                    }
+                    Some(BotMessage::UserState { channel, privileged }) => {
+                        rate_limiter.set_privileged(&channel, privileged);
+                    }
+                    Some(BotMessage::Reconnect) => {
+                        if migration.is_none() {
+                            info!("Opening new connection to Twitch IRC server");
+                            migration = Some(Box::pin(self.open_session()));
+                        }
+                    }
+                    Some(BotMessage::Join(channel)) => {
+                        let message = self.join(&channel);
+                        if migration.is_some() {
+                            replay.push(message.clone());
+                        }
+                        if let Err(error) = write_line(&mut write_half, &message).await {
+                            error!("Writing to TCP failed: {}", error);
+                            return SessionEnd::Disconnected;
+                        }
+                    }
+                    Some(BotMessage::Part(channel)) => {
+                        let message = self.part(&channel);
+                        if migration.is_some() {
+                            replay.push(message.clone());
+                        }
+                        if let Err(error) = write_line(&mut write_half, &message).await {
+                            error!("Writing to TCP failed: {}", error);
+                            return SessionEnd::Disconnected;
+                        }
+                    }
+                    None => return SessionEnd::BotClosed,
+                },
+                _ = tokio::time::sleep(next_ready_in.unwrap_or_default()), if next_ready_in.is_some() => {
+                    if let Err(error) = flush(&mut write_half, rate_limiter).await {
+                        error!("Writing to TCP failed: {}", error);
+                        return SessionEnd::Disconnected;
+                    }
+                },
+                session = async { migration.as_mut().unwrap().await }, if migration.is_some() => {
+                    migration = None;
+                    match session {
+                        Ok((new_lines, new_write_half)) => {
+                            info!("Migrated to new connection, closing the old one");
+                            lines = new_lines;
+                            write_half = new_write_half;
+                            for message in replay.drain(..) {
+                                if let Err(error) = write_line(&mut write_half, &message).await {
+                                    error!("Writing to TCP failed: {}", error);
+                                    return SessionEnd::Disconnected;
+                                }
+                            }
+                        }
+                        Err(error) => {
+                            error!("Opening new connection failed: {}", error);
+                            replay.clear();
+                        }
+                    }
+                },
             }
-        });
+        }
+    }
+
+    async fn authenticate(&self, write_half: &mut IrcWriter) -> std::io::Result<()> {
+        write_line(write_half, &format!("PASS {}", self.oauth_token)).await?;
+        write_line(write_half, &format!("NICK {}", self.nickname)).await?;
+        write_line(write_half, "CAP REQ :twitch.tv/tags twitch.tv/commands").await?;
+        let channels = self.channels.lock().expect("Failed to lock channels").clone();
+        if channels.is_empty() {
+            return Ok(());
+        }
+        let channels: Vec<String> = channels
+            .iter()
+            .map(|channel| format!("#{}", channel))
+            .collect();
+        write_line(write_half, &format!("JOIN {}", channels.join(","))).await
+    }
+
+    /// Remembers `channel` for future logins and returns the JOIN line.
+    fn join(&self, channel: &str) -> String {
+        let channel = channel_name(channel);
+        let mut channels = self.channels.lock().expect("Failed to lock channels");
+        if !channels.contains(&channel) {
+            channels.push(channel.clone());
+        }
+        info!("Joining #{}", channel);
+        format!("JOIN #{}", channel)
+    }
+
+    /// Forgets `channel` for future logins and returns the PART line.
+    fn part(&self, channel: &str) -> String {
+        let channel = channel_name(channel);
+        self.channels.lock().expect("Failed to lock channels").retain(|joined| *joined != channel);
+        info!("Leaving #{}", channel);
+        format!("PART #{}", channel)
+    }
+}
+
+async fn write_line(write_half: &mut IrcWriter, message: &str) -> std::io::Result<()> {
+    write_half.write_all(format!("{}\r\n", message).as_bytes()).await
+}
+
+/// Writes every queued chat message the rate limit currently allows.
+async fn flush(write_half: &mut IrcWriter, rate_limiter: &mut RateLimiter) -> std::io::Result<()> {
+    while let Some(message) = rate_limiter.pop_ready() {
+        if let Err(error) = write_line(write_half, &message.to_string()).await {
+            rate_limiter.requeue(message);
+            return Err(error);
+        }
+    }
+    Ok(())
+}
+
+/// Exponential backoff between reconnect attempts. Each delay is picked at
+/// random between half the initial delay and a ceiling that doubles with
+/// every attempt, up to a minute.
+pub struct Backoff {
+    current: Duration,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Backoff {
+    pub fn new() -> Self {
+        Self { current: INITIAL_BACKOFF }
+    }
+
+    pub fn reset(&mut self) {
+        self.current = INITIAL_BACKOFF;
+    }
+
+    pub fn next_delay(&mut self) -> Duration {
+        let ceiling = self.current;
+        self.current = (self.current * 2).min(MAX_BACKOFF);
+        rand::thread_rng().gen_range(INITIAL_BACKOFF / 2..=ceiling)
     }
 }
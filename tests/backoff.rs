@@ -0,0 +1,30 @@
+use std::time::Duration;
+
+use cb_twitchchatbot_rust::tcp_handler::Backoff;
+
+#[test]
+fn keeps_delays_between_half_a_second_and_the_ceiling() {
+    let ceilings = [1, 2, 4, 8, 16, 32, 60, 60, 60];
+
+    for _ in 0..100 {
+        let mut backoff = Backoff::new();
+        for ceiling in ceilings {
+            let delay = backoff.next_delay();
+            assert!(delay >= Duration::from_millis(500), "{:?} is below the minimum", delay);
+            assert!(delay <= Duration::from_secs(ceiling), "{:?} is above {}s", delay, ceiling);
+        }
+    }
+}
+
+#[test]
+fn starts_over_after_a_reset() {
+    let mut backoff = Backoff::new();
+    for _ in 0..10 {
+        backoff.next_delay();
+    }
+
+    backoff.reset();
+
+    assert!(backoff.next_delay() <= Duration::from_secs(1));
+    assert!(backoff.next_delay() <= Duration::from_secs(2));
+}
@@ -0,0 +1,547 @@
+mod support;
+
+use cb_twitchchatbot_rust::{
+    chat_bot::ChatBot,
+    error::{ ConnectError, Error },
+    rate_limiter::OverflowPolicy,
+};
+use std::{ fs, time::Duration };
+
+use tokio::task::JoinHandle;
+
+use support::{ fake_server::{ user_id, FakeServer, TIMEOUT }, temp_file::TempFile };
+
+const COMMANDS_FILE: &str = "tests/fixtures/commands.json";
+
+fn start_bot(server: &FakeServer, channels: &[&str]) -> JoinHandle<Result<(), Error>> {
+    start_bot_with_commands(server, channels, COMMANDS_FILE)
+}
+
+fn start_bot_with_commands(
+    server: &FakeServer,
+    channels: &[&str],
+    commands_file: &str
+) -> JoinHandle<Result<(), Error>> {
+    let mut bot = ChatBot::new(
+        server.connection_config(),
+        "bot".to_string(),
+        "oauth:token".to_string(),
+        channels
+            .iter()
+            .map(|channel| channel.to_string())
+            .collect(),
+        commands_file.to_string(),
+        OverflowPolicy::default()
+    ).expect("Starting the bot failed");
+    tokio::spawn(async move { bot.run().await })
+}
+
+#[tokio::test]
+async fn logs_in_and_joins_channels() {
+    let server = FakeServer::start().await;
+    start_bot(&server, &["first", "#Second"]);
+
+    let mut connection = server.accept().await;
+    let login = connection.expect_login().await;
+
+    assert_eq!(login, [
+        "PASS oauth:token",
+        "NICK bot",
+        "CAP REQ :twitch.tv/tags twitch.tv/commands",
+        "JOIN #first,#second",
+    ]);
+}
+
+#[tokio::test]
+async fn answers_ping_with_pong() {
+    let server = FakeServer::start().await;
+    start_bot(&server, &["channel"]);
+
+    let mut connection = server.accept().await;
+    connection.expect_login().await;
+    connection.send_ping().await;
+
+    assert_eq!(connection.expect_line("PONG").await, "PONG tmi.twitch.tv");
+}
+
+#[tokio::test]
+async fn responds_to_commands_in_originating_channel() {
+    let server = FakeServer::start().await;
+    start_bot(&server, &["first", "second"]);
+
+    let mut connection = server.accept().await;
+    connection.expect_login().await;
+    connection.send_notice("#first", "This room is now in slow mode.").await;
+    connection.send_privmsg("second", "Viewer", "!hug Streamer").await;
+
+    assert_eq!(connection.expect_line("PRIVMSG").await, "PRIVMSG #second :Viewer hugs Streamer");
+}
+
+#[tokio::test]
+async fn thanks_for_resubs() {
+    let server = FakeServer::start().await;
+    start_bot(&server, &["channel"]);
+
+    let mut connection = server.accept().await;
+    connection.expect_login().await;
+    connection.send(
+        "@display-name=Viewer;msg-id=resub;msg-param-cumulative-months=12 :tmi.twitch.tv USERNOTICE #channel :Hi!"
+    ).await;
+
+    assert_eq!(
+        connection.expect_line("PRIVMSG").await,
+        "PRIVMSG #channel :Thanks Viewer for the 12-month resub!"
+    );
+}
+
+#[tokio::test]
+async fn answers_community_gifts_once() {
+    let server = FakeServer::start().await;
+    start_bot(&server, &["channel"]);
+
+    let mut connection = server.accept().await;
+    connection.expect_login().await;
+    connection.send(
+        "@display-name=Gifter;msg-id=submysterygift;msg-param-mass-gift-count=2;msg-param-community-gift-id=42 :tmi.twitch.tv USERNOTICE #channel"
+    ).await;
+    for recipient in ["First", "Second"] {
+        connection.send(
+            &format!(
+                "@display-name=Gifter;msg-id=subgift;msg-param-recipient-display-name={};msg-param-community-gift-id=42 :tmi.twitch.tv USERNOTICE #channel",
+                recipient
+            )
+        ).await;
+    }
+    connection.send(
+        "@display-name=Gifter;msg-id=subgift;msg-param-recipient-display-name=Third :tmi.twitch.tv USERNOTICE #channel"
+    ).await;
+
+    assert_eq!(
+        connection.expect_line("PRIVMSG").await,
+        "PRIVMSG #channel :Gifter is gifting 2 subs to the community!"
+    );
+    assert_eq!(connection.expect_line("PRIVMSG").await, "PRIVMSG #channel :Gifter gifted a sub to Third!");
+}
+
+#[tokio::test]
+async fn welcomes_first_time_chatters_once_per_cooldown() {
+    let server = FakeServer::start().await;
+    start_bot(&server, &["channel"]);
+
+    let mut connection = server.accept().await;
+    connection.expect_login().await;
+    connection.send_privmsg("channel", "Regular", "hello again").await;
+    for (user_id, display_name) in [("2", "Newcomer"), ("3", "Raider")] {
+        connection.send(
+            &format!(
+                "@display-name={display_name};first-msg=1;user-id={user_id} :viewer!viewer@viewer.tmi.twitch.tv PRIVMSG #channel :hi"
+            )
+        ).await;
+    }
+    connection.send_privmsg("channel", "Viewer", "!ping").await;
+
+    assert_eq!(
+        connection.expect_line("PRIVMSG").await,
+        "PRIVMSG #channel :Welcome to the channel Newcomer!"
+    );
+    assert_eq!(connection.expect_line("PRIVMSG").await, "PRIVMSG #channel :pong");
+}
+
+#[tokio::test]
+async fn welcomes_newcomers_whose_first_message_is_a_command() {
+    let server = FakeServer::start().await;
+    start_bot(&server, &["channel"]);
+
+    let mut connection = server.accept().await;
+    connection.expect_login().await;
+    connection.send(
+        "@display-name=Newcomer;first-msg=1;user-id=2 :newcomer!newcomer@newcomer.tmi.twitch.tv PRIVMSG #channel :!ping"
+    ).await;
+
+    assert_eq!(
+        connection.expect_line("PRIVMSG").await,
+        "PRIVMSG #channel :Welcome to the channel Newcomer!"
+    );
+    assert_eq!(connection.expect_line("PRIVMSG").await, "PRIVMSG #channel :pong");
+}
+
+#[tokio::test]
+async fn welcomes_returning_chatters_again_after_rejoining() {
+    let commands_file = TempFile::new(
+        "first_in_stream",
+        r#"{
+            "commands": [{ "name": "ping", "response": "pong" }],
+            "welcomes": [{ "response": "Hi {sender}!", "first_in_stream": true }]
+        }"#
+    );
+    let server = FakeServer::start().await;
+    start_bot_with_commands(&server, &["channel"], commands_file.path());
+
+    let mut connection = server.accept().await;
+    connection.expect_login().await;
+    connection.send(":bot!bot@bot.tmi.twitch.tv JOIN #channel").await;
+    connection.send_privmsg("channel", "Viewer", "hello").await;
+    connection.send_privmsg("channel", "Viewer", "hello again").await;
+    connection.send_privmsg("channel", "Other", "!ping").await;
+
+    assert_eq!(connection.expect_line("PRIVMSG").await, "PRIVMSG #channel :Hi Viewer!");
+    assert_eq!(connection.expect_line("PRIVMSG").await, "PRIVMSG #channel :Hi Other!");
+    assert_eq!(connection.expect_line("PRIVMSG").await, "PRIVMSG #channel :pong");
+
+    connection.close().await;
+    let mut connection = server.accept().await;
+    connection.expect_login().await;
+    connection.send(":bot!bot@bot.tmi.twitch.tv JOIN #channel").await;
+    connection.send_privmsg("channel", "Viewer", "back again").await;
+
+    assert_eq!(connection.expect_line("PRIVMSG").await, "PRIVMSG #channel :Hi Viewer!");
+}
+
+#[tokio::test]
+async fn restricts_commands_by_role_and_denies_once() {
+    let server = FakeServer::start().await;
+    start_bot(&server, &["channel"]);
+
+    let mut connection = server.accept().await;
+    connection.expect_login().await;
+    connection.send_privmsg("channel", "Viewer", "!so Streamer").await;
+    connection.send_privmsg("channel", "Viewer", "!so Streamer").await;
+    connection.send(
+        "@badges=moderator/1;display-name=Mod;mod=1;user-id=2 :mod!mod@mod.tmi.twitch.tv PRIVMSG #channel :!so Streamer"
+    ).await;
+
+    assert_eq!(
+        connection.expect_line("PRIVMSG").await,
+        "PRIVMSG #channel :Sorry Viewer, only moderators can give shoutouts."
+    );
+    assert_eq!(connection.expect_line("PRIVMSG").await, "PRIVMSG #channel :Go check out Streamer!");
+}
+
+#[tokio::test]
+async fn applies_channel_cooldowns_per_channel() {
+    let server = FakeServer::start().await;
+    start_bot(&server, &["first", "second"]);
+
+    let mut connection = server.accept().await;
+    connection.expect_login().await;
+    connection.send_privmsg("first", "Viewer", "!roll").await;
+    connection.send_privmsg("second", "Other", "!roll").await;
+    connection.send_privmsg("first", "Other", "!roll").await;
+    connection.send_privmsg("first", "Viewer", "!ping").await;
+
+    assert_eq!(connection.expect_line("PRIVMSG").await, "PRIVMSG #first :Viewer rolls the dice!");
+    assert_eq!(connection.expect_line("PRIVMSG").await, "PRIVMSG #second :Other rolls the dice!");
+    assert_eq!(connection.expect_line("PRIVMSG").await, "PRIVMSG #first :pong");
+}
+
+#[tokio::test]
+async fn keeps_legacy_global_cooldowns_per_channel() {
+    let server = FakeServer::start().await;
+    start_bot(&server, &["first", "second"]);
+
+    let mut connection = server.accept().await;
+    connection.expect_login().await;
+    connection.send_privmsg("first", "Viewer", "!ping").await;
+    connection.send_privmsg("first", "Other", "!ping").await;
+    connection.send_privmsg("second", "Other", "!ping").await;
+
+    assert_eq!(connection.expect_line("PRIVMSG").await, "PRIVMSG #first :pong");
+    assert_eq!(connection.expect_line("PRIVMSG").await, "PRIVMSG #second :pong");
+}
+
+#[tokio::test]
+async fn lets_moderators_bypass_cooldowns() {
+    let commands_file = TempFile::new(
+        "bypass",
+        r#"{
+            "cooldown_bypass": "bypass_keep_timer",
+            "commands": [
+                { "name": "ping", "response": "pong", "cooldowns": { "global": "10s" } },
+                { "name": "hug {name}", "response": "{sender} hugs {name}", "cooldowns": { "user": "20s" } }
+            ]
+        }"#
+    );
+    let server = FakeServer::start().await;
+    start_bot_with_commands(&server, &["channel"], commands_file.path());
+
+    let mut connection = server.accept().await;
+    connection.expect_login().await;
+    connection.send_privmsg("channel", "Viewer", "!ping").await;
+    connection.send(
+        "@badges=moderator/1;display-name=Mod;mod=1;user-id=2 :mod!mod@mod.tmi.twitch.tv PRIVMSG #channel :!ping"
+    ).await;
+    connection.send_privmsg("channel", "Viewer", "!ping").await;
+    connection.send_privmsg("channel", "Viewer", "!hug Streamer").await;
+
+    assert_eq!(connection.expect_line("PRIVMSG").await, "PRIVMSG #channel :pong");
+    assert_eq!(connection.expect_line("PRIVMSG").await, "PRIVMSG #channel :pong");
+    assert_eq!(connection.expect_line("PRIVMSG").await, "PRIVMSG #channel :Viewer hugs Streamer");
+}
+
+#[tokio::test]
+async fn tells_users_about_cooldowns_once_per_channel() {
+    let server = FakeServer::start().await;
+    start_bot(&server, &["channel"]);
+
+    let mut connection = server.accept().await;
+    connection.expect_login().await;
+    connection.send_privmsg("channel", "Viewer", "!codewars").await;
+    connection.send_privmsg("channel", "Other", "!codewars").await;
+    connection.send_privmsg("channel", "Other", "!codewars").await;
+    connection.send_privmsg("channel", "Third", "!codewars").await;
+    connection.send_privmsg("channel", "Other", "!ping").await;
+
+    assert_eq!(connection.expect_line("PRIVMSG").await, "PRIVMSG #channel :Time for a round of codewars!");
+    let notice = connection.expect_line("PRIVMSG").await;
+    assert!(
+        notice.starts_with("PRIVMSG #channel :Other, !codewars is on cooldown for another "),
+        "Unexpected notice: {}",
+        notice
+    );
+    assert_eq!(connection.expect_line("PRIVMSG").await, "PRIVMSG #channel :pong");
+}
+
+#[tokio::test]
+async fn restores_cooldowns_after_restart() {
+    let now = cb_twitchchatbot_rust::messages::cooldown::current_time();
+    let state_file = TempFile::new(
+        "cooldowns",
+        &format!(
+            r#"[{{ "key": {{ "Channel": {{ "channel": "channel", "command": "codewars" }} }}, "triggered_at": {} }}]"#,
+            now
+        )
+    );
+
+    let server = FakeServer::start().await;
+    let mut bot = ChatBot::new(
+        server.connection_config(),
+        "bot".to_string(),
+        "oauth:token".to_string(),
+        vec!["channel".to_string()],
+        COMMANDS_FILE.to_string(),
+        OverflowPolicy::default()
+    ).unwrap();
+    bot.persist_cooldowns(state_file.path());
+    tokio::spawn(async move { bot.run().await });
+
+    let mut connection = server.accept().await;
+    connection.expect_login().await;
+    connection.send_privmsg("channel", "Viewer", "!codewars").await;
+
+    let notice = connection.expect_line("PRIVMSG").await;
+    assert!(
+        notice.starts_with("PRIVMSG #channel :Viewer, !codewars is on cooldown"),
+        "Unexpected notice: {}",
+        notice
+    );
+}
+
+#[tokio::test]
+async fn keeps_user_cooldowns_across_renames() {
+    let server = FakeServer::start().await;
+    start_bot(&server, &["channel"]);
+
+    let mut connection = server.accept().await;
+    connection.expect_login().await;
+    connection.send_privmsg("channel", "Viewer", "!hug Streamer").await;
+    connection.send(
+        &format!(
+            "@display-name=NewName;user-id={} :newname!newname@newname.tmi.twitch.tv PRIVMSG #channel :!hug Streamer",
+            user_id("Viewer")
+        )
+    ).await;
+    connection.send_privmsg("channel", "Viewer", "!ping").await;
+
+    assert_eq!(connection.expect_line("PRIVMSG").await, "PRIVMSG #channel :Viewer hugs Streamer");
+    assert_eq!(connection.expect_line("PRIVMSG").await, "PRIVMSG #channel :pong");
+}
+
+#[tokio::test]
+async fn aliases_share_the_command_cooldown() {
+    let server = FakeServer::start().await;
+    start_bot(&server, &["channel"]);
+
+    let mut connection = server.accept().await;
+    connection.expect_login().await;
+    connection.send_privmsg("channel", "Viewer", "!cuddle Streamer").await;
+    connection.send_privmsg("channel", "Viewer", "!hug Streamer").await;
+    connection.send_privmsg("channel", "Viewer", "!ping").await;
+
+    assert_eq!(connection.expect_line("PRIVMSG").await, "PRIVMSG #channel :Viewer hugs Streamer");
+    assert_eq!(connection.expect_line("PRIVMSG").await, "PRIVMSG #channel :pong");
+}
+
+#[tokio::test]
+async fn reloads_commands_when_file_changes() {
+    let lurk_command = |response: &str| {
+        format!(
+            r#"[{{ "name": "lurk", "response": "{}", "cooldown_in_s": "0", "cooldown_scope": "global" }}]"#,
+            response
+        )
+    };
+    let commands_file = TempFile::new("reload", &lurk_command("Enjoy the lurk"));
+
+    let server = FakeServer::start().await;
+    start_bot_with_commands(&server, &["channel"], commands_file.path());
+    let mut connection = server.accept().await;
+    connection.expect_login().await;
+
+    connection.send_privmsg("channel", "Viewer", "!lurk").await;
+    assert_eq!(connection.expect_line("PRIVMSG").await, "PRIVMSG #channel :Enjoy the lurk");
+
+    // Broken edits keep the previous commands active
+    fs::write(commands_file.path(), "[{").unwrap();
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    connection.send_privmsg("channel", "Viewer", "!lurk").await;
+    assert_eq!(connection.expect_line("PRIVMSG").await, "PRIVMSG #channel :Enjoy the lurk");
+
+    fs::write(commands_file.path(), lurk_command("Thanks for lurking")).unwrap();
+    let deadline = tokio::time::Instant::now() + TIMEOUT;
+    loop {
+        connection.send_privmsg("channel", "Viewer", "!lurk").await;
+        if connection.expect_line("PRIVMSG").await == "PRIVMSG #channel :Thanks for lurking" {
+            break;
+        }
+        assert!(tokio::time::Instant::now() < deadline, "Commands were not reloaded");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+}
+
+#[tokio::test]
+async fn migrates_to_new_connection_on_reconnect() {
+    let server = FakeServer::start().await;
+    start_bot(&server, &["channel"]);
+
+    let mut old_connection = server.accept().await;
+    old_connection.expect_login().await;
+    old_connection.send_reconnect().await;
+
+    let mut new_connection = server.accept().await;
+    new_connection.expect_login().await;
+    assert_eq!(old_connection.next_line().await, None);
+
+    new_connection.send_privmsg("channel", "Viewer", "!ping").await;
+    assert_eq!(new_connection.expect_line("PRIVMSG").await, "PRIVMSG #channel :pong");
+}
+
+#[tokio::test]
+async fn reconnects_after_connection_is_closed() {
+    let server = FakeServer::start().await;
+    start_bot(&server, &["channel"]);
+
+    let mut connection = server.accept().await;
+    connection.expect_login().await;
+    connection.close().await;
+
+    let mut connection = server.accept().await;
+    connection.expect_login().await;
+    connection.send_privmsg("channel", "Viewer", "!ping").await;
+
+    assert_eq!(connection.expect_line("PRIVMSG").await, "PRIVMSG #channel :pong");
+    assert!(connection.received().iter().any(|line| line == "NICK bot"));
+}
+
+#[tokio::test]
+async fn joins_and_parts_channels_at_runtime() {
+    let server = FakeServer::start().await;
+    let mut bot = ChatBot::new(
+        server.connection_config(),
+        "bot".to_string(),
+        "oauth:token".to_string(),
+        vec!["first".to_string(), "second".to_string()],
+        COMMANDS_FILE.to_string(),
+        OverflowPolicy::default()
+    ).unwrap();
+    let handle = bot.handle();
+    tokio::spawn(async move { bot.run().await });
+
+    let mut connection = server.accept().await;
+    connection.expect_login().await;
+    handle.join("#Third").unwrap();
+    handle.part("second").unwrap();
+
+    assert_eq!(connection.expect_line("JOIN").await, "JOIN #third");
+    assert_eq!(connection.expect_line("PART").await, "PART #second");
+
+    connection.close().await;
+    let mut connection = server.accept().await;
+    let login = connection.expect_login().await;
+
+    assert_eq!(login.last().unwrap(), "JOIN #first,#third");
+}
+
+#[tokio::test]
+async fn stops_with_error_when_authentication_fails() {
+    let server = FakeServer::start().await;
+    let bot = start_bot(&server, &["channel"]);
+
+    let mut connection = server.accept().await;
+    connection.read_login().await;
+    connection.send_notice("*", "Login authentication failed").await;
+    connection.close().await;
+
+    let result = tokio::time::timeout(TIMEOUT, bot).await.expect("Bot did not stop").unwrap();
+    assert!(
+        matches!(
+            result,
+            Err(Error::Connect(ConnectError::AuthenticationFailed(ref notice)))
+                if notice == "Login authentication failed"
+        )
+    );
+}
+
+#[tokio::test]
+async fn stops_connecting_when_the_bot_is_dropped_during_login() {
+    let server = FakeServer::start().await;
+    let bot = ChatBot::new(
+        server.connection_config(),
+        "bot".to_string(),
+        "oauth:token".to_string(),
+        vec!["channel".to_string()],
+        COMMANDS_FILE.to_string(),
+        OverflowPolicy::default()
+    ).unwrap();
+
+    let mut connection = server.accept().await;
+    connection.read_login().await;
+    drop(bot);
+    connection.send(":tmi.twitch.tv CAP * ACK :twitch.tv/tags twitch.tv/commands").await;
+
+    assert_eq!(connection.next_line().await, None);
+    server.expect_no_connection(Duration::from_secs(2)).await;
+}
+
+#[tokio::test]
+async fn keeps_running_after_malformed_lines() {
+    let server = FakeServer::start().await;
+    start_bot(&server, &["channel"]);
+
+    let mut connection = server.accept().await;
+    connection.expect_login().await;
+    connection.send("").await;
+    connection.send("@emotes=25:0 :viewer!viewer@viewer.tmi.twitch.tv PRIVMSG #channel :Kappa").await;
+    connection.send_privmsg("channel", "Viewer", "!").await;
+    connection.send(
+        "@display-name=Viewer;emotes=25:x-y :viewer!viewer@viewer.tmi.twitch.tv PRIVMSG #channel :!ping Kappa"
+    ).await;
+    connection.send_ping().await;
+
+    assert_eq!(connection.expect_line("PRIVMSG").await, "PRIVMSG #channel :pong");
+    assert_eq!(connection.expect_line("PONG").await, "PONG tmi.twitch.tv");
+}
+
+#[tokio::test]
+async fn fails_to_start_without_commands_file() {
+    let server = FakeServer::start().await;
+
+    let result = ChatBot::new(
+        server.connection_config(),
+        "bot".to_string(),
+        "oauth:token".to_string(),
+        vec!["channel".to_string()],
+        "assets/missing.json".to_string(),
+        OverflowPolicy::default()
+    );
+
+    assert!(matches!(result, Err(Error::ReadConfig { .. })));
+}
@@ -0,0 +1,215 @@
+mod support;
+
+use std::time::Duration;
+
+use cb_twitchchatbot_rust::{
+    config::command_parser::{ Commands, CooldownBypass, CooldownScope, Permission },
+    error::Error,
+};
+
+use support::temp_file::TempFile;
+
+#[test]
+fn reads_plain_command_list() {
+    let file = TempFile::new(
+        "plain",
+        r#"[{ "name": "ping", "response": "pong", "cooldown_in_s": "10", "cooldown_scope": "global" }]"#
+    );
+
+    let commands = Commands::new(file.path()).unwrap();
+
+    assert_eq!(commands.get().len(), 1);
+    assert!(commands.event_response("raid", "#channel").is_none());
+}
+
+#[test]
+fn reads_event_responses() {
+    let file = TempFile::new(
+        "events",
+        r##"{
+            "commands": [],
+            "events": [
+                { "event": "raid", "response": "{sender} brought {viewers} viewers", "channels": ["#first"] }
+            ]
+        }"##
+    );
+
+    let commands = Commands::new(file.path()).unwrap();
+
+    assert!(commands.event_response("raid", "first").is_some());
+    assert!(commands.event_response("raid", "second").is_none());
+}
+
+#[test]
+fn rejects_unknown_event_placeholders() {
+    let file = TempFile::new(
+        "unknown_placeholder",
+        r#"{ "commands": [], "events": [{ "event": "sub", "response": "{sender} raided with {viewers}" }] }"#
+    );
+
+    let result = Commands::new(file.path());
+
+    assert!(matches!(result, Err(Error::InvalidCommand { name, .. }) if name == "sub"));
+}
+
+#[test]
+fn reads_command_permissions() {
+    let file = TempFile::new(
+        "permissions",
+        r#"[
+            { "name": "a", "response": "a", "cooldown_in_s": "0", "cooldown_scope": "global" },
+            { "name": "b", "response": "b", "cooldown_in_s": "0", "cooldown_scope": "global", "permission": "vip" },
+            { "name": "c", "response": "c", "cooldown_in_s": "0", "cooldown_scope": "global", "permission": { "users": ["42"] } }
+        ]"#
+    );
+
+    let commands = Commands::new(file.path()).unwrap();
+    let permissions: Vec<_> = commands
+        .get()
+        .iter()
+        .map(|command| command.permission.clone())
+        .collect();
+
+    assert_eq!(permissions, [
+        Permission::Everyone,
+        Permission::Vip,
+        Permission::Users(vec!["42".to_string()]),
+    ]);
+}
+
+#[test]
+fn reads_cooldowns_in_every_format() {
+    let file = TempFile::new(
+        "cooldowns",
+        r#"[
+            { "name": "a", "response": "a", "cooldown_in_s": "30", "cooldown_scope": "user" },
+            { "name": "b", "response": "b", "cooldown": 30, "cooldown_scope": "global" },
+            { "name": "c", "response": "c", "cooldown": "45s", "cooldown_scope": "global" },
+            { "name": "d", "response": "d", "cooldown": "5m", "cooldown_scope": "global" },
+            { "name": "e", "response": "e", "cooldown_in_s": "60", "cooldown_scope": "global" }
+        ]"#
+    );
+
+    let commands = Commands::new(file.path()).unwrap();
+    let cooldowns: Vec<_> = commands
+        .get()
+        .iter()
+        .flat_map(|command| command.cooldowns.iter())
+        .collect();
+
+    // The global scope of legacy cooldown_in_s files is tracked per channel,
+    // as it was before combined cooldowns existed
+    assert_eq!(cooldowns, [
+        (CooldownScope::User, Duration::from_secs(30)),
+        (CooldownScope::Global, Duration::from_secs(30)),
+        (CooldownScope::Global, Duration::from_secs(45)),
+        (CooldownScope::Global, Duration::from_secs(300)),
+        (CooldownScope::Channel, Duration::from_secs(60)),
+    ]);
+}
+
+#[test]
+fn reads_combined_cooldowns() {
+    let file = TempFile::new(
+        "combined_cooldowns",
+        r#"[{ "name": "a", "response": "a", "cooldowns": { "global": "5s", "channel": 10, "user": "1m" } }]"#
+    );
+
+    let commands = Commands::new(file.path()).unwrap();
+    let cooldowns: Vec<_> = commands.get()[0].cooldowns.iter().collect();
+
+    assert_eq!(cooldowns, [
+        (CooldownScope::Global, Duration::from_secs(5)),
+        (CooldownScope::Channel, Duration::from_secs(10)),
+        (CooldownScope::User, Duration::from_secs(60)),
+    ]);
+}
+
+#[test]
+fn rejects_invalid_cooldowns() {
+    for (name, command) in [
+        ("bad_duration", r#"{ "name": "a", "response": "a", "cooldown": "soon", "cooldown_scope": "user" }"#),
+        ("bad_unit", r#"{ "name": "a", "response": "a", "cooldown": "5d", "cooldown_scope": "user" }"#),
+        ("bad_scope", r#"{ "name": "a", "response": "a", "cooldown": "5", "cooldown_scope": "everyone" }"#),
+        ("missing_scope", r#"{ "name": "a", "response": "a", "cooldown": "5" }"#),
+        (
+            "cooldown_set_twice",
+            r#"{ "name": "a", "response": "a", "cooldown": "5", "cooldown_in_s": "5", "cooldown_scope": "user" }"#,
+        ),
+        (
+            "scope_set_twice",
+            r#"{ "name": "a", "response": "a", "cooldown": "5", "cooldown_scope": "user", "cooldowns": { "user": 5 } }"#,
+        ),
+    ] {
+        let file = TempFile::new(name, &format!("[{}]", command));
+
+        let result = Commands::new(file.path());
+
+        assert!(matches!(result, Err(Error::ParseConfig { .. })), "{} was accepted", name);
+    }
+}
+
+#[test]
+fn commands_override_the_file_wide_cooldown_bypass() {
+    let file = TempFile::new(
+        "cooldown_bypass",
+        r#"{
+            "cooldown_bypass": "bypass",
+            "commands": [
+                { "name": "a", "response": "a" },
+                { "name": "b", "response": "b", "cooldown_bypass": "off" }
+            ]
+        }"#
+    );
+
+    let commands = Commands::new(file.path()).unwrap();
+    let bypasses: Vec<_> = commands
+        .get()
+        .iter()
+        .map(|command| commands.cooldown_bypass(command))
+        .collect();
+
+    assert_eq!(bypasses, [CooldownBypass::Bypass, CooldownBypass::Off]);
+}
+
+#[test]
+fn rejects_aliases_colliding_with_other_commands() {
+    for (name, commands) in [
+        (
+            "alias_of_trigger",
+            r#"[{ "name": "hug {name}", "response": "a" }, { "name": "b", "response": "b", "aliases": ["hug"] }]"#,
+        ),
+        (
+            "alias_of_alias",
+            r#"[{ "name": "a", "response": "a", "aliases": ["x"] }, { "name": "b", "response": "b", "aliases": ["!x"] }]"#,
+        ),
+    ] {
+        let file = TempFile::new(name, commands);
+
+        let result = Commands::new(file.path());
+
+        assert!(matches!(result, Err(Error::InvalidCommand { .. })), "{} was accepted", name);
+    }
+}
+
+#[test]
+fn allows_the_same_alias_in_separate_channels() {
+    let file = TempFile::new(
+        "alias_per_channel",
+        r#"[
+            { "name": "a", "response": "a", "aliases": ["x"], "channels": ["first"] },
+            { "name": "b", "response": "b", "aliases": ["x"], "channels": ["second"] }
+        ]"#
+    );
+
+    let commands = Commands::new(file.path()).unwrap();
+
+    assert_eq!(commands.get()[1].aliases, ["x"]);
+}
+
+#[test]
+fn loads_the_shipped_commands_file() {
+    let commands = Commands::new("assets/commands.json").unwrap();
+
+    assert!(commands.get().iter().any(|command| command.trigger() == "ping"));
+}
@@ -0,0 +1,149 @@
+use std::{ sync::{ Arc, Mutex }, time::Duration };
+
+use cb_twitchchatbot_rust::{
+    config::command_parser::{ Cooldown, CooldownScope, Welcome },
+    messages::{
+        bot_command::format_remaining,
+        cooldown::{ Clock, CooldownKey, CooldownTracker, NOTICE_COOLDOWN },
+        tags::PrivmsgTags,
+        welcome::Welcomer,
+    },
+};
+use tokio::time::Instant;
+
+/// Clock that only moves when the test advances it.
+struct ManualClock(Mutex<Instant>);
+
+impl ManualClock {
+    fn advance(&self, duration: Duration) {
+        *self.0.lock().unwrap() += duration;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        *self.0.lock().unwrap()
+    }
+}
+
+fn tracker() -> (CooldownTracker, Arc<ManualClock>) {
+    let clock = Arc::new(ManualClock(Mutex::new(Instant::now())));
+    (CooldownTracker::with_clock(clock.clone()), clock)
+}
+
+fn key(scope: CooldownScope, user_id: &str) -> CooldownKey {
+    CooldownKey::new(scope, "dice", "#channel", user_id)
+}
+
+#[test]
+fn blocks_until_cooldown_ran_out() {
+    let (cooldowns, clock) = tracker();
+    let global = || vec![(key(CooldownScope::Global, "1"), Duration::from_secs(10))];
+
+    assert!(cooldowns.trigger(global(), true).is_ok());
+    clock.advance(Duration::from_secs(4));
+    assert_eq!(
+        cooldowns.trigger(global(), true),
+        Err((key(CooldownScope::Global, "1"), Duration::from_secs(6)))
+    );
+    clock.advance(Duration::from_secs(6));
+    assert!(cooldowns.trigger(global(), true).is_ok());
+}
+
+#[test]
+fn rejected_uses_do_not_restart_passing_cooldowns() {
+    let (cooldowns, clock) = tracker();
+    let combined = |user_id| {
+        vec![
+            (key(CooldownScope::Global, user_id), Duration::from_secs(5)),
+            (key(CooldownScope::User, user_id), Duration::from_secs(60))
+        ]
+    };
+
+    assert!(cooldowns.trigger(combined("1"), true).is_ok());
+    clock.advance(Duration::from_secs(5));
+    // The global cooldown passed but the user one did not, so nothing is recorded
+    assert!(cooldowns.trigger(combined("1"), true).is_err());
+    assert!(cooldowns.trigger(combined("2"), true).is_ok());
+}
+
+#[test]
+fn unenforced_triggers_restart_cooldowns() {
+    let (cooldowns, clock) = tracker();
+    let global = || vec![(key(CooldownScope::Global, "1"), Duration::from_secs(10))];
+
+    assert!(cooldowns.trigger(global(), true).is_ok());
+    clock.advance(Duration::from_secs(8));
+    assert!(cooldowns.trigger(global(), false).is_ok());
+    clock.advance(Duration::from_secs(8));
+    assert!(cooldowns.trigger(global(), true).is_err());
+}
+
+#[test]
+fn notices_once_per_cooldown() {
+    let (cooldowns, clock) = tracker();
+    let global = || vec![(key(CooldownScope::Global, "1"), Duration::from_secs(10))];
+    let notice = || CooldownKey::notice("dice", "#channel", "2");
+    let channel_notice = || CooldownKey::channel_notice("dice", "#channel");
+
+    cooldowns.trigger(global(), true).unwrap();
+    assert!(cooldowns.notice(notice(), channel_notice(), &key(CooldownScope::Global, "1")));
+    assert!(!cooldowns.notice(notice(), channel_notice(), &key(CooldownScope::Global, "1")));
+
+    clock.advance(NOTICE_COOLDOWN);
+    cooldowns.trigger(global(), true).unwrap();
+    clock.advance(Duration::from_secs(1));
+    assert!(cooldowns.notice(notice(), channel_notice(), &key(CooldownScope::Global, "1")));
+}
+
+#[test]
+fn limits_notices_per_channel() {
+    let (cooldowns, clock) = tracker();
+    let global = vec![(key(CooldownScope::Global, "1"), Duration::from_secs(600))];
+    let notice = |user_id| CooldownKey::notice("dice", "#channel", user_id);
+    let channel_notice = |channel| CooldownKey::channel_notice("dice", channel);
+    let blocking_key = key(CooldownScope::Global, "1");
+
+    cooldowns.trigger(global, true).unwrap();
+    assert!(cooldowns.notice(notice("2"), channel_notice("#channel"), &blocking_key));
+    // Other users are not told until the channel's notice cooldown ran out
+    assert!(!cooldowns.notice(notice("3"), channel_notice("#channel"), &blocking_key));
+    assert!(cooldowns.notice(notice("3"), channel_notice("#other"), &blocking_key));
+
+    clock.advance(NOTICE_COOLDOWN);
+    assert!(cooldowns.notice(notice("4"), channel_notice("#channel"), &blocking_key));
+}
+
+#[test]
+fn rounds_remaining_time_up() {
+    assert_eq!(format_remaining(Duration::from_millis(400)), "1s");
+    assert_eq!(format_remaining(Duration::from_millis(44_100)), "45s");
+    assert_eq!(format_remaining(Duration::from_secs(60)), "1m");
+    assert_eq!(format_remaining(Duration::from_millis(59_001)), "1m");
+    assert_eq!(format_remaining(Duration::from_millis(244_500)), "4m 5s");
+}
+
+#[test]
+fn welcomes_once_per_cooldown() {
+    let (cooldowns, clock) = tracker();
+    let mut welcomer = Welcomer::with_clock(cooldowns.clock());
+    let welcome = Welcome {
+        response: "Hi {sender}!".to_string(),
+        cooldown: Cooldown(Duration::from_secs(30)),
+        first_in_stream: false,
+        channels: Vec::new(),
+    };
+    let newcomer = |name: &str| PrivmsgTags {
+        display_name: Some(name.to_string()),
+        first_msg: true,
+        ..Default::default()
+    };
+
+    assert!(welcomer.welcome(&welcome, &newcomer("Viewer"), "#channel").is_some());
+    clock.advance(Duration::from_secs(29));
+    assert!(welcomer.welcome(&welcome, &newcomer("Other"), "#channel").is_none());
+    assert!(welcomer.welcome(&welcome, &newcomer("Other"), "#other").is_some());
+
+    clock.advance(Duration::from_secs(1));
+    assert!(welcomer.welcome(&welcome, &newcomer("Third"), "#channel").is_some());
+}
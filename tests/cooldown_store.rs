@@ -0,0 +1,39 @@
+mod support;
+
+use std::fs;
+
+use cb_twitchchatbot_rust::{
+    config::command_parser::{ Commands, CooldownScope },
+    cooldown_store,
+    messages::cooldown::{ current_time, CooldownKey, CooldownTracker },
+};
+
+use support::temp_file::TempFile;
+
+#[test]
+fn saves_only_running_cooldowns() {
+    let commands = Commands::new("tests/fixtures/commands.json").unwrap();
+    let now = current_time();
+    let running = CooldownKey::new(CooldownScope::Channel, "codewars", "#channel", "1");
+    let expired = CooldownKey::new(CooldownScope::Channel, "ping", "#channel", "1");
+    let removed = CooldownKey::new(CooldownScope::Channel, "removed", "#channel", "1");
+
+    let cooldowns = CooldownTracker::default();
+    let restored = cooldowns.restore(
+        vec![(running.clone(), now), (expired, now - 3600), (removed, now)],
+        &commands
+    );
+    assert_eq!(restored, 1);
+
+    let state_file = TempFile::new("saved_cooldowns", "[]");
+    cooldown_store::save(state_file.path(), &cooldowns, &commands).unwrap();
+
+    let saved: serde_json::Value = serde_json::from_slice(&fs::read(state_file.path()).unwrap()).unwrap();
+    assert_eq!(saved.as_array().map(Vec::len), Some(1));
+    let kept: Vec<_> = cooldowns
+        .snapshot()
+        .into_iter()
+        .map(|(key, _)| key)
+        .collect();
+    assert_eq!(kept, [running]);
+}
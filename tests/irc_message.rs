@@ -0,0 +1,88 @@
+use std::borrow::Cow;
+
+use cb_twitchchatbot_rust::messages::{
+    irc_message::{ IrcMessage, IrcMessageRef },
+    private_message::PrivateMessageRequest,
+};
+
+#[test]
+fn splits_middle_and_trailing_parameters() {
+    let message = IrcMessage::parse(
+        "@id=1;flag :nick!user@host PRIVMSG #chan:nel :hello :world  "
+    ).unwrap();
+
+    assert_eq!(message.tags, [
+        ("id".to_string(), "1".to_string()),
+        ("flag".to_string(), String::new()),
+    ]);
+    assert_eq!(message.prefix.as_deref(), Some("nick!user@host"));
+    assert_eq!(message.command, "PRIVMSG");
+    assert_eq!(message.params, ["#chan:nel", "hello :world  "]);
+}
+
+#[test]
+fn parses_numeric_replies() {
+    let request = PrivateMessageRequest::new(
+        ":bot.tmi.twitch.tv 353 bot = #channel :bot viewer moderator"
+    ).unwrap();
+
+    let command = request.command.expect("Numeric reply was discarded");
+    assert_eq!(command.command, "353");
+    assert_eq!(command.channel.as_deref(), Some("#channel"));
+    assert_eq!(request.parameters.as_deref(), Some("bot viewer moderator"));
+}
+
+#[test]
+fn rejects_lines_without_command() {
+    assert!(IrcMessage::parse("").is_err());
+    assert!(IrcMessage::parse("@id=1 :prefix").is_err());
+    assert!(IrcMessage::parse(" @").is_err());
+}
+
+#[test]
+fn skips_tags_without_key() {
+    let message = IrcMessage::parse("@=;id=1 PING").unwrap();
+    assert_eq!(message.tags, vec![("id".to_string(), "1".to_string())]);
+}
+
+#[test]
+fn serializes_back_to_an_equal_message() {
+    for line in [
+        "PING :tmi.twitch.tv",
+        ":tmi.twitch.tv CAP * ACK :twitch.tv/tags twitch.tv/commands",
+        "@display-name=A\\sB;emotes= :a!a@a.tmi.twitch.tv PRIVMSG #channel ::starts with colon",
+        ":tmi.twitch.tv RECONNECT",
+        "PRIVMSG #channel :",
+    ] {
+        let message = IrcMessage::parse(line).unwrap();
+        let serialized = message.to_string();
+        assert_eq!(IrcMessage::parse(&serialized).unwrap(), message, "{}", serialized);
+    }
+
+    let message = IrcMessage::new("PRIVMSG", &["#channel", "hello world"]);
+    assert_eq!(message.to_string(), "PRIVMSG #channel :hello world");
+}
+
+#[test]
+fn borrowed_view_parses_lazily_without_copying() {
+    let line = "@display-name=Viewer;system-msg=a\\sb :nick!user@host PRIVMSG #channel :!hug someone";
+    let message = IrcMessageRef::parse(line).unwrap();
+
+    assert_eq!(message.command(), "PRIVMSG");
+    assert_eq!(message.prefix(), Some("nick!user@host"));
+    assert!(matches!(message.tag("display-name"), Some(Cow::Borrowed("Viewer"))));
+    assert!(matches!(message.tag("system-msg"), Some(Cow::Owned(ref value)) if value == "a b"));
+    assert_eq!(message.param(0), Some("#channel"));
+    assert_eq!(message.last_param(), Some("!hug someone"));
+    assert_eq!(message.to_owned_message(), IrcMessage::parse(line).unwrap());
+}
+
+#[test]
+fn takes_the_last_value_of_duplicate_tags() {
+    let line = "@display-name=First;display-name=Second :nick!user@host PRIVMSG #channel :hi";
+
+    assert_eq!(IrcMessage::parse(line).unwrap().tag("display-name"), Some("Second"));
+    assert_eq!(IrcMessageRef::parse(line).unwrap().tag("display-name").as_deref(), Some("Second"));
+    let request = PrivateMessageRequest::new(line).unwrap();
+    assert_eq!(request.tags.as_ref().and_then(|tags| tags.display_name()), Some("Second"));
+}
@@ -0,0 +1,98 @@
+use cb_twitchchatbot_rust::messages::{
+    irc_message::{ IrcMessage, IrcMessageRef },
+    private_message::PrivateMessageRequest,
+    tags::{ escape_tag_value, unescape_tag_value },
+};
+use proptest::prelude::*;
+
+fn tag() -> impl Strategy<Value = (String, String)> {
+    ("[a-z][a-z0-9-]{0,15}", "[^\0]{0,20}")
+}
+
+fn middle_param() -> impl Strategy<Value = String> {
+    "[^ :\r\n\0][^ \r\n\0]{0,15}"
+}
+
+fn irc_message() -> impl Strategy<Value = IrcMessage> {
+    (
+        prop::collection::vec(tag(), 0..6),
+        prop::option::of("[^ @:\r\n\0][^ \r\n\0]{0,20}"),
+        prop_oneof!["[A-Z]{1,12}", "[0-9]{3}"],
+        prop::collection::vec(middle_param(), 0..4),
+        prop::option::of("[^\r\n\0]{0,40}"),
+    ).prop_map(|(mut tags, prefix, command, mut params, trailing)| {
+        // Later duplicates win when looking tags up, keep keys unique for equality
+        tags.dedup_by(|(a, _), (b, _)| a == b);
+        params.extend(trailing);
+        IrcMessage { tags, prefix, command, params }
+    })
+}
+
+/// Lines shaped like Twitch traffic, with arbitrary bytes in every component.
+fn twitch_like_line() -> impl Strategy<Value = String> {
+    (
+        prop::option::of("[^ ]{0,60}"),
+        prop::option::of("[^ ]{0,30}"),
+        prop_oneof![
+            Just("PRIVMSG".to_string()),
+            Just("USERNOTICE".to_string()),
+            Just("CLEARCHAT".to_string()),
+            Just("ROOMSTATE".to_string()),
+            Just("USERSTATE".to_string()),
+            Just("CAP".to_string()),
+            Just("353".to_string()),
+            ".{0,10}",
+        ],
+        ".{0,60}",
+    ).prop_map(|(tags, prefix, command, rest)| {
+        let mut line = String::new();
+        if let Some(tags) = tags {
+            line.push_str(&format!("@{} ", tags));
+        }
+        if let Some(prefix) = prefix {
+            line.push_str(&format!(":{} ", prefix));
+        }
+        line.push_str(&command);
+        line.push(' ');
+        line.push_str(&rest);
+        line
+    })
+}
+
+proptest! {
+    #[test]
+    fn private_message_request_never_panics(line in ".{0,200}") {
+        let _ = PrivateMessageRequest::new(&line);
+    }
+
+    #[test]
+    fn private_message_request_never_panics_on_twitch_like_lines(line in twitch_like_line()) {
+        let _ = PrivateMessageRequest::new(&line);
+    }
+
+    #[test]
+    fn serialized_messages_parse_back(message in irc_message()) {
+        let serialized = message.to_string();
+        prop_assert_eq!(IrcMessage::parse(&serialized).unwrap(), message);
+    }
+
+    #[test]
+    fn parsing_is_stable_after_serializing(line in "[^\r\n]{0,200}") {
+        if let Ok(message) = IrcMessage::parse(&line) {
+            let reparsed = IrcMessage::parse(&message.to_string()).unwrap();
+            prop_assert_eq!(reparsed, message);
+        }
+    }
+
+    #[test]
+    fn borrowed_and_owned_parsers_agree(line in twitch_like_line()) {
+        let owned = IrcMessage::parse(&line).ok();
+        let borrowed = IrcMessageRef::parse(&line).ok().map(|message| message.to_owned_message());
+        prop_assert_eq!(borrowed, owned);
+    }
+
+    #[test]
+    fn tag_values_round_trip(value in ".{0,50}") {
+        prop_assert_eq!(unescape_tag_value(&escape_tag_value(&value)), value);
+    }
+}
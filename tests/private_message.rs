@@ -0,0 +1,121 @@
+use cb_twitchchatbot_rust::messages::{
+    private_message::{ PrivateMessageRequest, PrivateMessageResponse },
+    tags::{ escape_tag_value, unescape_tag_value, Tags },
+    user_notice::{ SubPlan, UserNoticeEvent },
+};
+
+#[test]
+fn types_privmsg_tags() {
+    let request = PrivateMessageRequest::new(
+        "@badge-info=subscriber/8;badges=moderator/1,subscriber/6;color=#0D4200;display-name=Viewer;emotes=25:0-4,12-16/1902:6-10;first-msg=1;mod=1;reply-parent-msg-id=abc;tmi-sent-ts=1507246572675;user-id=1337;client-nonce=xyz :viewer!viewer@viewer.tmi.twitch.tv PRIVMSG #channel :Kappa Keepo Kappa"
+    ).unwrap();
+
+    let Some(Tags::Privmsg(tags)) = request.tags else {
+        panic!("Expected PRIVMSG tags, got {:?}", request.tags);
+    };
+    assert_eq!(tags.display_name.as_deref(), Some("Viewer"));
+    assert_eq!(tags.user_id.as_deref(), Some("1337"));
+    assert!(tags.badges.contains("moderator"));
+    assert_eq!(tags.badge_info.version("subscriber"), Some("8"));
+    assert_eq!(tags.emotes.len(), 2);
+    assert_eq!(tags.emotes[0].positions[1].start_position, 12);
+    assert!(tags.first_msg && tags.moderator && !tags.subscriber);
+    assert_eq!(tags.tmi_sent_ts, Some(1_507_246_572_675));
+    assert_eq!(tags.reply_parent.map(|parent| parent.msg_id).as_deref(), Some("abc"));
+    assert_eq!(tags.other.get("client-nonce").map(String::as_str), Some("xyz"));
+}
+
+#[test]
+fn keeps_malformed_emotes_in_other_tags() {
+    let request = PrivateMessageRequest::new(
+        "@display-name=Viewer;emotes=25:0 :viewer!viewer@viewer.tmi.twitch.tv PRIVMSG #channel :!ping Kappa"
+    ).unwrap();
+
+    let Some(Tags::Privmsg(tags)) = request.tags else {
+        panic!("Expected PRIVMSG tags, got {:?}", request.tags);
+    };
+    assert!(tags.emotes.is_empty());
+    assert_eq!(tags.other.get("emotes").map(String::as_str), Some("25:0"));
+    assert!(request.command.and_then(|command| command.bot_command).is_some());
+}
+
+#[test]
+fn types_roomstate_tags() {
+    let request = PrivateMessageRequest::new(
+        "@emote-only=0;followers-only=-1;r9k=0;room-id=12345678;slow=10;subs-only=1 :tmi.twitch.tv ROOMSTATE #channel"
+    ).unwrap();
+
+    let Some(Tags::RoomState(tags)) = request.tags else {
+        panic!("Expected ROOMSTATE tags, got {:?}", request.tags);
+    };
+    assert_eq!(tags.emote_only, Some(false));
+    assert_eq!(tags.followers_only, Some(-1));
+    assert_eq!(tags.slow, Some(10));
+    assert_eq!(tags.subs_only, Some(true));
+}
+
+#[test]
+fn unescapes_tag_values() {
+    let request = PrivateMessageRequest::new(
+        r"@display-name=Viewer;msg-id=resub;system-msg=Viewer\ssubscribed\sfor\s5\smonths\:\sthanks\\!;msg-param-cumulative-months=5 :tmi.twitch.tv USERNOTICE #channel"
+    ).unwrap();
+
+    let Some(Tags::UserNotice(tags)) = request.tags else {
+        panic!("Expected USERNOTICE tags, got {:?}", request.tags);
+    };
+    assert_eq!(tags.system_msg.as_deref(), Some(r"Viewer subscribed for 5 months; thanks\!"));
+    assert_eq!(tags.msg_params.get("cumulative-months").map(String::as_str), Some("5"));
+}
+
+#[test]
+fn types_user_notice_events() {
+    let request = PrivateMessageRequest::new(
+        "@display-name=Viewer;msg-id=resub;msg-param-cumulative-months=6;msg-param-streak-months=2;msg-param-sub-plan=2000 :tmi.twitch.tv USERNOTICE #channel :Great stream!"
+    ).unwrap();
+
+    let command = request.command.unwrap();
+    assert_eq!(command.channel.as_deref(), Some("#channel"));
+    assert_eq!(request.parameters.as_deref(), Some("Great stream!"));
+    assert_eq!(
+        command.user_notice,
+        Some(UserNoticeEvent::Resub { plan: SubPlan::Tier2, months: 6, streak_months: 2 })
+    );
+
+    let request = PrivateMessageRequest::new(
+        "@display-name=Raider;msg-id=raid;msg-param-viewerCount=42 :tmi.twitch.tv USERNOTICE #channel"
+    ).unwrap();
+    let event = request.command.unwrap().user_notice.unwrap();
+    assert_eq!(event, UserNoticeEvent::Raid { viewer_count: 42 });
+    assert_eq!(
+        event.render("{sender} raids with {viewers} viewers", "Raider", ""),
+        "Raider raids with 42 viewers"
+    );
+}
+
+#[test]
+fn inserts_user_text_into_event_responses_verbatim() {
+    let event = UserNoticeEvent::Resub { plan: SubPlan::Tier1, months: 6, streak_months: 0 };
+
+    let response = event.render("{sender} resubbed for {months} months: {message}", "{months}", "I love {months}");
+
+    assert_eq!(response, "{months} resubbed for 6 months: I love {months}");
+}
+
+#[test]
+fn escapes_reply_tags() {
+    let reply = PrivateMessageResponse::reply("#channel", "pong", "id with; spaces\\");
+
+    assert_eq!(
+        reply.to_string(),
+        r"@reply-parent-msg-id=id\swith\:\sspaces\\ PRIVMSG #channel :pong"
+    );
+}
+
+#[test]
+fn tag_escaping_round_trips() {
+    for value in ["plain", "a b;c\\d\r\ne", "\\s\\:", ""] {
+        assert_eq!(unescape_tag_value(&escape_tag_value(value)), value);
+    }
+    assert_eq!(unescape_tag_value(r"trailing\"), "trailing");
+    assert_eq!(unescape_tag_value(r"\b"), "b");
+}
@@ -0,0 +1,83 @@
+use std::time::Duration;
+
+use cb_twitchchatbot_rust::{
+    messages::private_message::PrivateMessageResponse,
+    rate_limiter::{ OverflowPolicy, RateLimiter },
+};
+
+/// Flushes the queue after submitting each of `count` messages to `channel`,
+/// like the connection does, and returns how many were sent.
+fn send(rate_limiter: &mut RateLimiter, channel: &str, count: usize) -> usize {
+    let mut sent = std::iter::from_fn(|| rate_limiter.pop_ready()).count();
+    for index in 0..count {
+        rate_limiter.submit(PrivateMessageResponse::from(channel, &index.to_string()));
+        sent += std::iter::from_fn(|| rate_limiter.pop_ready()).count();
+    }
+    sent
+}
+
+#[tokio::test(start_paused = true)]
+async fn never_exceeds_the_limit_within_any_window() {
+    let mut rate_limiter = RateLimiter::new(OverflowPolicy::Queue(1000));
+    let mut sent_at = vec![];
+
+    rate_limiter.submit(PrivateMessageResponse::from("#channel", "first"));
+    for second in 0..120 {
+        for _ in 0..3 {
+            rate_limiter.submit(PrivateMessageResponse::from("#channel", "message"));
+        }
+        while rate_limiter.pop_ready().is_some() {
+            sent_at.push(second);
+        }
+        tokio::time::advance(Duration::from_secs(1)).await;
+    }
+
+    for window_start in 0..90 {
+        let in_window = sent_at
+            .iter()
+            .filter(|second| (window_start..window_start + 30).contains(*second))
+            .count();
+        assert!(in_window <= 20, "{} messages sent in the window at {}s", in_window, window_start);
+    }
+    assert_eq!(sent_at.len(), 80);
+}
+
+#[tokio::test(start_paused = true)]
+async fn sends_queued_messages_once_the_window_moves_on() {
+    let mut rate_limiter = RateLimiter::new(OverflowPolicy::Queue(5));
+
+    assert_eq!(send(&mut rate_limiter, "#channel", 30), 20);
+    assert_eq!(rate_limiter.next_ready_in(), Some(Duration::from_secs(30)));
+
+    tokio::time::advance(Duration::from_secs(30)).await;
+
+    assert_eq!(send(&mut rate_limiter, "#channel", 0), 5);
+    assert_eq!(rate_limiter.next_ready_in(), None);
+}
+
+#[tokio::test(start_paused = true)]
+async fn drops_messages_over_the_limit() {
+    let mut rate_limiter = RateLimiter::new(OverflowPolicy::Drop);
+
+    assert_eq!(send(&mut rate_limiter, "#channel", 30), 20);
+    assert_eq!(rate_limiter.next_ready_in(), None);
+
+    tokio::time::advance(Duration::from_secs(30)).await;
+
+    assert_eq!(send(&mut rate_limiter, "#channel", 1), 1);
+}
+
+#[tokio::test(start_paused = true)]
+async fn uses_the_larger_budget_in_privileged_channels() {
+    let mut rate_limiter = RateLimiter::new(OverflowPolicy::Drop);
+    rate_limiter.set_privileged("#moderated", true);
+
+    assert_eq!(send(&mut rate_limiter, "#moderated", 120), 100);
+    // Privileged messages count against the shared budget as well
+    assert_eq!(send(&mut rate_limiter, "#channel", 1), 0);
+
+    tokio::time::advance(Duration::from_secs(30)).await;
+
+    assert_eq!(send(&mut rate_limiter, "#channel", 30), 20);
+    assert_eq!(send(&mut rate_limiter, "#moderated", 100), 80);
+}
@@ -0,0 +1,146 @@
+//! Scriptable stand-in for Twitch's IRC server, listening on localhost over plain TCP.
+
+use std::{ collections::hash_map::DefaultHasher, hash::{ Hash, Hasher }, time::Duration };
+
+use cb_twitchchatbot_rust::config::connection::ConnectionConfig;
+use tokio::{
+    io::{ AsyncBufReadExt, AsyncWriteExt, BufReader, Lines },
+    net::{ tcp::{ OwnedReadHalf, OwnedWriteHalf }, TcpListener },
+};
+
+/// How long to wait for the bot before a test fails.
+pub const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Stable user id for a display name, distinct for different names.
+pub fn user_id(display_name: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    display_name.to_lowercase().hash(&mut hasher);
+    (hasher.finish() % 1_000_000_000).to_string()
+}
+
+pub struct FakeServer {
+    listener: TcpListener,
+}
+
+impl FakeServer {
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("Binding fake server failed");
+        Self { listener }
+    }
+
+    /// Connection settings pointing the bot at this server.
+    pub fn connection_config(&self) -> ConnectionConfig {
+        let address = self.listener.local_addr().expect("Fake server has no address");
+        ConnectionConfig {
+            host: address.ip().to_string(),
+            port: address.port(),
+            tls: false,
+        }
+    }
+
+    /// Waits for the bot to open the next connection.
+    pub async fn accept(&self) -> FakeConnection {
+        let (stream, _) = tokio::time
+            ::timeout(TIMEOUT, self.listener.accept()).await
+            .expect("Bot did not connect in time")
+            .expect("Accepting connection failed");
+        let (read_half, write_half) = stream.into_split();
+        FakeConnection {
+            lines: BufReader::new(read_half).lines(),
+            writer: write_half,
+            received: Vec::new(),
+        }
+    }
+
+    /// Fails if the bot opens a connection within `wait`.
+    pub async fn expect_no_connection(&self, wait: Duration) {
+        if let Ok(accepted) = tokio::time::timeout(wait, self.listener.accept()).await {
+            panic!("Bot connected unexpectedly: {:?}", accepted.map(|(_, address)| address));
+        }
+    }
+}
+
+/// One connection from the bot, recording every line it sends.
+pub struct FakeConnection {
+    lines: Lines<BufReader<OwnedReadHalf>>,
+    writer: OwnedWriteHalf,
+    received: Vec<String>,
+}
+
+impl FakeConnection {
+    /// Reads the next line from the bot, `None` once it closed the connection.
+    pub async fn next_line(&mut self) -> Option<String> {
+        let line = tokio::time
+            ::timeout(TIMEOUT, self.lines.next_line()).await
+            .expect("Bot did not send anything in time")
+            .expect("Reading from bot failed")?;
+        self.received.push(line.clone());
+        Some(line)
+    }
+
+    /// Reads lines until one starts with `prefix` and returns it.
+    pub async fn expect_line(&mut self, prefix: &str) -> String {
+        loop {
+            match self.next_line().await {
+                Some(line) if line.starts_with(prefix) => return line,
+                Some(_) => {}
+                None => panic!("Bot closed the connection while waiting for {:?}", prefix),
+            }
+        }
+    }
+
+    /// Reads the PASS/NICK/CAP/JOIN login sequence without answering it.
+    pub async fn read_login(&mut self) -> Vec<String> {
+        let mut login = Vec::new();
+        for prefix in ["PASS ", "NICK ", "CAP REQ ", "JOIN "] {
+            login.push(self.expect_line(prefix).await);
+        }
+        login
+    }
+
+    /// Reads the login sequence and accepts it.
+    pub async fn expect_login(&mut self) -> Vec<String> {
+        let login = self.read_login().await;
+        self.send(":tmi.twitch.tv CAP * ACK :twitch.tv/tags twitch.tv/commands").await;
+        self.send(":tmi.twitch.tv 001 bot :Welcome, GLHF!").await;
+        login
+    }
+
+    /// Everything the bot sent on this connection so far.
+    pub fn received(&self) -> &[String] {
+        &self.received
+    }
+
+    pub async fn send(&mut self, line: &str) {
+        self.writer
+            .write_all(format!("{}\r\n", line).as_bytes()).await
+            .expect("Writing to bot failed");
+    }
+
+    pub async fn send_privmsg(&mut self, channel: &str, display_name: &str, text: &str) {
+        let nick = display_name.to_lowercase();
+        let user_id = user_id(display_name);
+        self.send(
+            &format!(
+                "@badges=;display-name={display_name};user-id={user_id} :{nick}!{nick}@{nick}.tmi.twitch.tv PRIVMSG #{channel} :{text}"
+            )
+        ).await;
+    }
+
+    pub async fn send_ping(&mut self) {
+        self.send("PING :tmi.twitch.tv").await;
+    }
+
+    pub async fn send_reconnect(&mut self) {
+        self.send(":tmi.twitch.tv RECONNECT").await;
+    }
+
+    pub async fn send_notice(&mut self, channel: &str, text: &str) {
+        self.send(&format!(":tmi.twitch.tv NOTICE {} :{}", channel, text)).await;
+    }
+
+    /// Closes the connection from the server side.
+    pub async fn close(mut self) {
+        self.writer.shutdown().await.expect("Closing connection failed");
+    }
+}
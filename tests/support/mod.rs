@@ -0,0 +1,5 @@
+// Every test binary only uses part of the support code
+#![allow(dead_code)]
+
+pub mod fake_server;
+pub mod temp_file;
@@ -0,0 +1,25 @@
+//! Files in the temp directory that are removed again when the test ends.
+
+use std::{ fs, path::PathBuf };
+
+pub struct TempFile(PathBuf);
+
+impl TempFile {
+    /// Writes `contents` to a file named after `name` and the test process.
+    pub fn new(name: &str, contents: &str) -> Self {
+        let file_name = format!("cb_twitchchatbot_{}_{}.json", name, std::process::id());
+        let path = std::env::temp_dir().join(file_name);
+        fs::write(&path, contents).expect("Writing temp file failed");
+        Self(path)
+    }
+
+    pub fn path(&self) -> &str {
+        self.0.to_str().expect("Temp file path is not UTF-8")
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}